@@ -0,0 +1,398 @@
+use crate::boid::{Boid, BoidPlugin, Settings, Velocity};
+use crate::player::components::Player;
+use crate::schedule::SimulationSet;
+use crate::steering::Neighbourhood;
+use crate::trail::{FollowEntity, SimpleTrail2D};
+use bevy::prelude::*;
+use bevy::render::mesh::{Indices, PrimitiveTopology};
+use bevy::render::render_asset::RenderAssetUsages;
+use bevy::sprite::Mesh2dHandle;
+use bevy::utils::HashMap;
+use bevy_spatial::kdtree::KDTree2;
+use std::f32::consts::TAU;
+
+#[allow(unused_imports)]
+pub mod prelude {
+    pub use super::{
+        AppearancePlugin, BoidAssets, BoidShape, BoidSize, ColourMode, ColourRamp, Tint,
+    };
+}
+
+/// Number of materials the colour ramp is split into, boids snap to the nearest one
+const RAMP_STEPS: usize = 32;
+
+pub struct AppearancePlugin;
+
+impl Plugin for AppearancePlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<Tint>().register_type::<BoidSize>();
+        app.add_systems(
+            Update,
+            (apply_tints, colour_boids, scale_boids)
+                .chain()
+                .in_set(SimulationSet::RenderSync),
+        );
+    }
+
+    fn finish(&self, app: &mut App) {
+        // Mesh and material assets only exist once the render plugins are built
+        app.init_resource::<BoidAssets>();
+    }
+}
+
+/// What a boid looks like. Shapes point up along +y, the direction the boid travels in
+#[derive(Clone, Default)]
+pub enum BoidShape {
+    /// The built-in triangle
+    #[default]
+    Triangle,
+    /// Convex polygon with its vertices in order around the outline
+    Polygon(Vec<Vec2>),
+    /// Any 2D mesh
+    Mesh(Mesh),
+    /// Image loaded from `assets/`, drawn on a rectangle of `size`.
+    /// The image colours are multiplied by the boid colour, so white images take it on fully
+    Sprite { path: String, size: Vec2 },
+}
+
+impl BoidShape {
+    /// The arrow sprite that ships in `assets/sprites`
+    pub fn arrow() -> Self {
+        Self::Sprite {
+            path: "sprites/arrow.png".into(),
+            size: Vec2::new(9.0, 8.0),
+        }
+    }
+
+    /// The circle sprite that ships in `assets/sprites`
+    pub fn circle() -> Self {
+        Self::Sprite {
+            path: "sprites/circle.png".into(),
+            size: Vec2::splat(6.0),
+        }
+    }
+
+    /// Build the mesh the shape is drawn with
+    pub fn mesh(&self) -> Mesh {
+        match self {
+            Self::Triangle => {
+                Triangle2d::new(Vec2::Y * 4.0, Vec2::new(-3.5, -4.0), Vec2::new(3.5, -4.0)).into()
+            }
+            Self::Polygon(vertices) => polygon_mesh(vertices),
+            Self::Mesh(mesh) => mesh.clone(),
+            Self::Sprite { size, .. } => Rectangle::from_size(*size).into(),
+        }
+    }
+}
+
+/// Triangle fan mesh from the first vertex of a convex polygon, with UVs over its bounds
+fn polygon_mesh(vertices: &[Vec2]) -> Mesh {
+    let min = vertices
+        .iter()
+        .copied()
+        .reduce(Vec2::min)
+        .unwrap_or_default();
+    let max = vertices
+        .iter()
+        .copied()
+        .reduce(Vec2::max)
+        .unwrap_or_default();
+    let size = (max - min).max(Vec2::splat(f32::EPSILON));
+    let positions: Vec<[f32; 3]> = vertices.iter().map(|v| [v.x, v.y, 0.0]).collect();
+    let uvs: Vec<[f32; 2]> = vertices
+        .iter()
+        .map(|v| {
+            let uv = (*v - min) / size;
+            [uv.x, 1.0 - uv.y]
+        })
+        .collect();
+    let indices = (1..vertices.len().saturating_sub(1) as u32)
+        .flat_map(|i| [0, i, i + 1])
+        .collect();
+    Mesh::new(
+        PrimitiveTopology::TriangleList,
+        RenderAssetUsages::default(),
+    )
+    .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, positions)
+    .with_inserted_attribute(
+        Mesh::ATTRIBUTE_NORMAL,
+        vec![[0.0, 0.0, 1.0]; vertices.len()],
+    )
+    .with_inserted_attribute(Mesh::ATTRIBUTE_UV_0, uvs)
+    .with_inserted_indices(Indices::U32(indices))
+}
+
+/// Scale of a boid before speed scaling, drawn from `BoidPlugin::size` on spawn
+#[derive(Component, Reflect, Clone, Copy)]
+#[reflect(Component)]
+pub struct BoidSize(pub f32);
+
+/// Mesh and palette materials shared by every boid, so they can be drawn in batches
+#[derive(Resource)]
+pub struct BoidAssets {
+    pub mesh: Mesh2dHandle,
+    /// Texture of sprite shapes, applied to every material
+    pub texture: Option<Handle<Image>>,
+    /// Back of the mesh at size 1, where the trail is attached
+    pub tail: Vec2,
+    /// One material per palette colour
+    pub palette: Vec<Handle<ColorMaterial>>,
+    /// Materials sampled evenly along `Settings::colour_ramp`
+    pub ramp: Vec<Handle<ColorMaterial>>,
+}
+
+impl BoidAssets {
+    /// Material of a palette entry, wrapping around the end of the palette
+    pub fn material(&self, index: usize) -> Handle<ColorMaterial> {
+        self.palette[index % self.palette.len()].clone()
+    }
+
+    /// Material of the ramp step closest to `t`, which goes from 0 to 1
+    pub fn ramp_material(&self, t: f32) -> Handle<ColorMaterial> {
+        self.ramp[ramp_step(t)].clone()
+    }
+
+    /// Change the colours of the palette, every boid using it updates without
+    /// touching the boids themselves. Extra colours add new palette entries
+    pub fn set_palette(&mut self, materials: &mut Assets<ColorMaterial>, colours: &[Color]) {
+        for (index, colour) in colours.iter().enumerate() {
+            match self.palette.get(index) {
+                Some(handle) => {
+                    if let Some(material) = materials.get_mut(handle) {
+                        material.color = *colour;
+                    }
+                }
+                None => self.palette.push(materials.add(ColorMaterial {
+                    color: *colour,
+                    texture: self.texture.clone(),
+                })),
+            }
+        }
+    }
+}
+
+impl FromWorld for BoidAssets {
+    fn from_world(world: &mut World) -> Self {
+        let config = world
+            .get_resource::<BoidPlugin>()
+            .cloned()
+            .unwrap_or_default();
+        let texture = match &config.shape {
+            BoidShape::Sprite { path, .. } => world
+                .get_resource::<AssetServer>()
+                .map(|asset_server| asset_server.load(path.clone())),
+            _ => None,
+        };
+        let mesh = config.shape.mesh();
+        let tail = mesh.compute_aabb().map_or(Vec2::ZERO, |aabb| {
+            Vec2::new(aabb.center.x, aabb.center.y - aabb.half_extents.y)
+        });
+        let mesh = Mesh2dHandle(world.resource_mut::<Assets<Mesh>>().add(mesh));
+
+        let mut materials = world.resource_mut::<Assets<ColorMaterial>>();
+        let mut material = |color: Color| {
+            materials.add(ColorMaterial {
+                color,
+                texture: texture.clone(),
+            })
+        };
+        let mut palette: Vec<_> = config.palette.iter().map(|c| material(*c)).collect();
+        if palette.is_empty() {
+            palette.push(material(Color::WHITE));
+        }
+        // Coloured from the settings when a colour mode is first selected
+        let ramp = (0..RAMP_STEPS).map(|_| material(Color::WHITE)).collect();
+        Self {
+            mesh,
+            texture,
+            tail,
+            palette,
+            ramp,
+        }
+    }
+}
+
+/// Index of the palette colour a boid is drawn with
+#[derive(Component, Reflect, Clone, Copy, Default, PartialEq, Eq)]
+#[reflect(Component)]
+pub struct Tint(pub usize);
+
+/// Swap the shared material of boids whose tint changed
+pub fn apply_tints(
+    mut query: Query<(&Tint, &mut Handle<ColorMaterial>), Changed<Tint>>,
+    boid_assets: Res<BoidAssets>,
+) {
+    for (tint, mut material) in query.iter_mut() {
+        let palette_material = boid_assets.material(tint.0);
+        if *material != palette_material {
+            *material = palette_material;
+        }
+    }
+}
+
+/// Per-boid quantity the boids are coloured by
+#[derive(Reflect, Clone, Copy, Default, PartialEq, Debug)]
+pub enum ColourMode {
+    /// Use the palette colour of each boid
+    #[default]
+    Palette,
+    /// Speed relative to the max speed
+    Speed,
+    /// Direction of travel, once around the ramp for a full turn
+    Heading,
+    /// Neighbours in the visual radius weighted by the cohesion kernel
+    Density,
+    /// Number of neighbours in the visual radius
+    NeighbourCount,
+    /// Neighbours in the separation radius weighted by the separation kernel
+    SeparationPressure,
+}
+
+/// Colours spaced evenly from 0 to 1, blended in between
+#[derive(Reflect, Clone, PartialEq, Debug)]
+pub struct ColourRamp {
+    pub colours: Vec<Color>,
+}
+
+impl Default for ColourRamp {
+    fn default() -> Self {
+        // Viridis
+        Self::new(vec![
+            Color::srgb_u8(68, 1, 84),
+            Color::srgb_u8(59, 82, 139),
+            Color::srgb_u8(33, 145, 140),
+            Color::srgb_u8(94, 201, 98),
+            Color::srgb_u8(253, 231, 37),
+        ])
+    }
+}
+
+impl ColourRamp {
+    pub fn new(colours: Vec<Color>) -> Self {
+        Self { colours }
+    }
+
+    /// Colour at `t`, which is clamped between 0 and 1
+    pub fn sample(&self, t: f32) -> Color {
+        match self.colours.len() {
+            0 => Color::WHITE,
+            1 => self.colours[0],
+            len => {
+                let t = if t.is_nan() { 0.0 } else { t.clamp(0.0, 1.0) };
+                let scaled = t * (len - 1) as f32;
+                let index = (scaled as usize).min(len - 2);
+                // Blend in Oklab so the ramp looks even
+                let start = Oklaba::from(self.colours[index]);
+                let end = Oklaba::from(self.colours[index + 1]);
+                start.mix(&end, scaled - index as f32).into()
+            }
+        }
+    }
+}
+
+/// Index of the ramp step closest to `t`
+fn ramp_step(t: f32) -> usize {
+    let t = if t.is_nan() { 0.0 } else { t.clamp(0.0, 1.0) };
+    (t * (RAMP_STEPS - 1) as f32).round() as usize
+}
+
+/// Colour boids, and optionally their trails, by the quantity selected in `Settings::colour_mode`
+#[allow(clippy::too_many_arguments)]
+pub fn colour_boids(
+    settings: Res<Settings>,
+    boid_assets: Res<BoidAssets>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    treeaccess: Res<KDTree2<Boid>>,
+    neighbour_query: Query<(&Transform, &Velocity, &Boid)>,
+    mut boid_query: Query<
+        (
+            Entity,
+            &Transform,
+            &Velocity,
+            Option<&Tint>,
+            &mut Handle<ColorMaterial>,
+        ),
+        (With<Boid>, Without<Player>),
+    >,
+    mut trail_query: Query<(&mut SimpleTrail2D, &FollowEntity)>,
+    mut previous_mode: Local<ColourMode>,
+) {
+    let mode = settings.colour_mode;
+    if mode == ColourMode::Palette {
+        if *previous_mode != ColourMode::Palette {
+            // Back to the palette colours
+            for (_, _, _, tint, mut material) in boid_query.iter_mut() {
+                *material = boid_assets.material(tint.copied().unwrap_or_default().0);
+            }
+            for (mut trail, _) in trail_query.iter_mut() {
+                trail.tint = None;
+            }
+        }
+        *previous_mode = mode;
+        return;
+    }
+
+    if settings.is_changed() || *previous_mode == ColourMode::Palette {
+        for (step, handle) in boid_assets.ramp.iter().enumerate() {
+            let colour = settings
+                .colour_ramp
+                .sample(step as f32 / (RAMP_STEPS - 1) as f32);
+            if let Some(material) = materials.get_mut(handle) {
+                material.color = colour;
+            }
+        }
+    }
+    *previous_mode = mode;
+
+    let scale = settings.colour_scale.max(f32::EPSILON);
+    let mut trail_tints: HashMap<Entity, f32> = HashMap::new();
+    for (entity, transform, velocity, _, mut material) in boid_query.iter_mut() {
+        let t = match mode {
+            ColourMode::Speed => velocity.0.length() / settings.max_speed.max(f32::EPSILON),
+            ColourMode::Heading => velocity.0.to_angle().rem_euclid(TAU) / TAU,
+            _ => {
+                let position = transform.translation.truncate();
+                let neighbourhood =
+                    Neighbourhood::gather(position, &treeaccess, &neighbour_query, &settings);
+                let value = match mode {
+                    ColourMode::Density => neighbourhood.cohesion_influence,
+                    ColourMode::NeighbourCount => neighbourhood.count as f32,
+                    _ => neighbourhood.separation_influence,
+                };
+                value / scale
+            }
+        };
+        let ramp_material = boid_assets.ramp_material(t);
+        if *material != ramp_material {
+            *material = ramp_material;
+        }
+        if settings.colour_trails {
+            trail_tints.insert(entity, t);
+        }
+    }
+
+    for (mut trail, follow_entity) in trail_query.iter_mut() {
+        let tint = trail_tints
+            .get(&follow_entity.0)
+            .map(|t| settings.colour_ramp.sample(*t));
+        // Only write when it changes so the trail mesh colours aren't rebuilt needlessly
+        if trail.tint != tint {
+            trail.tint = tint;
+        }
+    }
+}
+
+/// Scale boids by their size, and by their speed if `Settings::speed_scaling` is set
+pub fn scale_boids(
+    mut query: Query<(&BoidSize, &Velocity, &mut Transform)>,
+    settings: Res<Settings>,
+) {
+    let max_speed = settings.max_speed.max(f32::EPSILON);
+    for (size, velocity, mut transform) in query.iter_mut() {
+        let speed_factor = 1.0 + settings.speed_scaling * velocity.0.length() / max_speed;
+        let scale = Vec2::splat(size.0 * speed_factor).extend(1.0);
+        if transform.scale != scale {
+            transform.scale = scale;
+        }
+    }
+}
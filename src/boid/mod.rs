@@ -1,398 +1,1065 @@
-use crate::constants::{BOID_COUNT, ENEMY_SPEED};
+use crate::appearance::{
+    AppearancePlugin, BoidAssets, BoidShape, BoidSize, ColourMode, ColourRamp, Tint,
+};
+use crate::constants::ENEMY_SPEED;
+use crate::debug::{DebugOverlay, DebugPlugin};
+use crate::diagnostics::NumericalDiagnosticsPlugin;
+use crate::emitter::EmitterPlugin;
+use crate::flow_field::{FlowField, FlowFieldPlugin};
+use crate::formation::FormationPlugin;
+use crate::integration::Integrator;
+use crate::models::{model_is, CollectiveModelsPlugin, FlockingModel};
+use crate::path::PathPlugin;
 use crate::player::components::Player;
+use crate::player::PlayerPlugin;
+use crate::random::{standard_normal, SimulationRng};
+use crate::schedule::{configure_sets, SimulationSet, SteerSet};
+use crate::spawn::{SpawnPattern, SpawnVelocity};
+use crate::steering::{InfluenceKernel, Neighbourhood, Steering, SteeringPlugin};
 use crate::trail::prelude::*;
+use bevy::ecs::system::SystemParam;
 use bevy::prelude::*;
 use bevy::sprite::{MaterialMesh2dBundle, Mesh2dHandle};
 use bevy::window::PrimaryWindow;
 use bevy_inspector_egui::prelude::*;
-use bevy_spatial::{kdtree::KDTree2, SpatialAccess};
-use rand::random;
+use bevy_spatial::{kdtree::KDTree2, AutomaticUpdate, SpatialAccess, SpatialStructure};
+use rand::rngs::StdRng;
+use rand::Rng;
+use std::time::Duration;
 
-pub struct BoidPlugin;
+/// Adds the boid simulation. Configure the initial flock with the builder methods
+#[derive(Resource, Clone)]
+pub struct BoidPlugin {
+    /// How many boids are spawned at startup
+    pub boid_count: u32,
+    /// World space area the boids are spawned in, None uses the window
+    pub spawn_area: Option<Rect>,
+    /// How the boids are laid out inside the spawn area
+    pub spawn_pattern: SpawnPattern,
+    /// Initial heading and speed of the boids
+    pub spawn_velocity: SpawnVelocity,
+    /// What every boid looks like
+    pub shape: BoidShape,
+    /// Distribution of the per-boid size, 1 is the size of the shape
+    pub size: SpawnDistribution,
+    /// Colours the boids are drawn with, each boid picks one at random.
+    /// Boids with the same colour share a material
+    pub palette: Vec<Color>,
+    /// Colour of the boid trails
+    pub trail_colour: TrailColour,
+    /// Whether to add the PlayerPlugin and spawn a player
+    pub spawn_player: bool,
+    /// Give every boid a FlockForces component recording its flocking forces
+    pub record_forces: bool,
+}
+
+impl Default for BoidPlugin {
+    fn default() -> Self {
+        Self {
+            boid_count: 1000,
+            spawn_area: None,
+            spawn_pattern: SpawnPattern::default(),
+            spawn_velocity: SpawnVelocity::default(),
+            shape: BoidShape::Triangle,
+            size: SpawnDistribution::new(1.0, 0.0),
+            palette: vec![Color::srgb_u8(255, 221, 0)],
+            trail_colour: TrailColour::gradient(
+                Color::srgba_u8(255, 55, 0, 255),
+                Color::srgba_u8(255, 0, 0, 0),
+            ),
+            spawn_player: true,
+            record_forces: false,
+        }
+    }
+}
+
+impl BoidPlugin {
+    /// Create a new BoidPlugin with default values
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set how many boids are spawned at startup
+    pub fn with_boid_count(mut self, boid_count: u32) -> Self {
+        self.boid_count = boid_count;
+        self
+    }
 
-#[derive(Component, Clone)]
+    /// Set the world space area the boids are spawned in
+    pub fn with_spawn_area(mut self, spawn_area: Rect) -> Self {
+        self.spawn_area = Some(spawn_area);
+        self
+    }
+
+    /// Set how the boids are laid out inside the spawn area
+    pub fn with_spawn_pattern(mut self, spawn_pattern: SpawnPattern) -> Self {
+        self.spawn_pattern = spawn_pattern;
+        self
+    }
+
+    /// Set the initial heading and speed of the boids
+    pub fn with_spawn_velocity(mut self, spawn_velocity: SpawnVelocity) -> Self {
+        self.spawn_velocity = spawn_velocity;
+        self
+    }
+
+    /// Set what every boid looks like
+    pub fn with_shape(mut self, shape: BoidShape) -> Self {
+        self.shape = shape;
+        self
+    }
+
+    /// Set the mesh every boid is drawn with
+    pub fn with_mesh(mut self, mesh: impl Into<Mesh>) -> Self {
+        self.shape = BoidShape::Mesh(mesh.into());
+        self
+    }
+
+    /// Vary the size of the boids around `mean`, 1 is the size of the shape
+    pub fn with_size(mut self, mean: f32, variance: f32) -> Self {
+        self.size = SpawnDistribution::new(mean, variance);
+        self
+    }
+
+    /// Draw every boid with a single colour
+    pub fn with_colour(mut self, colour: Color) -> Self {
+        self.palette = vec![colour];
+        self
+    }
+
+    /// Set the colours the boids pick from
+    pub fn with_palette(mut self, palette: Vec<Color>) -> Self {
+        self.palette = palette;
+        self
+    }
+
+    /// Set the colour of the boid trails
+    pub fn with_trail_colour(mut self, trail_colour: TrailColour) -> Self {
+        self.trail_colour = trail_colour;
+        self
+    }
+
+    /// Set whether to spawn a player
+    pub fn with_player(mut self, spawn_player: bool) -> Self {
+        self.spawn_player = spawn_player;
+        self
+    }
+
+    /// Set whether every boid records its flocking forces in a FlockForces component
+    pub fn with_recorded_forces(mut self, record_forces: bool) -> Self {
+        self.record_forces = record_forces;
+        self
+    }
+}
+
+#[derive(Component, Reflect, Clone)]
+#[reflect(Component)]
 pub struct Velocity(pub Vec2);
 
-#[derive(Component, Clone)]
+#[derive(Component, Reflect, Clone)]
+#[reflect(Component)]
 pub struct MaxVelocity(pub f32);
 
-#[derive(Component)]
+/// Max angular velocity in radians per second, limits how fast Velocity can change direction
+#[derive(Component, Reflect, Clone)]
+#[reflect(Component)]
+pub struct MaxTurnRate(pub f32);
+
+/// Steering force applied this tick. The resulting acceleration is this divided by Mass
+#[derive(Component, Reflect)]
+#[reflect(Component)]
 pub struct Acceleration(pub Vec2);
 
+/// Mass of an entity, entities without one have a mass of 1. Opt-in: nothing adds it and it
+/// is not derived from `Boid::weight`, which only sets how much a boid counts for in its
+/// neighbours' averages. A heavy boid such as the player steers the flock without being
+/// slow to steer itself
+#[derive(Component, Reflect, Clone)]
+#[reflect(Component)]
+pub struct Mass(pub f32);
+
+impl Mass {
+    /// Inverse of the mass, massless entities are treated as very light rather than dividing by 0
+    pub fn inverse(mass: Option<&Mass>) -> f32 {
+        mass.map_or(1.0, |mass| 1.0 / mass.0.max(f32::EPSILON))
+    }
+}
+
+/// Area the border force keeps the boids inside, what the camera shows of the window.
+/// None when there is no camera or window, such as in headless apps
+#[derive(Resource, Default, Clone, Copy)]
+pub struct SimulationBounds(pub Option<Rect>);
+
 /// Simulation settings; everything can be updated through UI except the number of boids
 #[derive(Resource, Reflect, InspectorOptions)]
 #[reflect(Resource, InspectorOptions)]
 pub struct Settings {
+    /// Freeze the simulation, toggled with Space. Needs the TimeControlPlugin
+    pub paused: bool,
+    /// Advance the simulation by a single tick and pause, also triggered with Period.
+    /// Needs the TimeControlPlugin
+    pub step: bool,
+    /// Simulation speed relative to real time, halved and doubled with [ and ].
+    /// Needs the TimeControlPlugin
+    #[inspector(min = 0., max = 16., speed = 0.01)]
+    pub time_scale: f32,
+    /// Rule set used to move the flock
+    pub model: FlockingModel,
+    /// Vicsek model: width of the uniform angular noise added to each heading, in radians
+    #[inspector(min = 0., max = 6.3, speed = 0.01)]
+    pub vicsek_noise: f32,
+    /// Couzin model: outer radius of the zone of orientation. The zone of repulsion is the
+    /// separation radius and the zone of attraction extends to the visual radius
+    #[inspector(min = 0., max = 10000., speed = 10.)]
+    pub couzin_orientation_radius: f32,
     /// Radius of the circle in which boids can see
     #[inspector(min = 0., max = 10000., speed = 100.)]
-    visual_radius: f32,
+    pub visual_radius: f32,
     /// Radius of the circle in which boids wants to be alone
     #[inspector(min = 0., max = 1000., speed = 10.)]
-    separation_radius: f32,
+    pub separation_radius: f32,
     /// Cohesion rule : boids move toward the center of mass of their neighbors
     #[inspector(min = 0., max = 1., speed = 0.01)]
-    cohesion: f32,
+    pub cohesion: f32,
     /// Separation rule: boids move away from other boids that are in protected range
     #[inspector(min = 0., max = 1., speed = 0.01)]
-    separation: f32,
+    pub separation: f32,
     /// Alignment rule: boids try to match the average velocity of boids located in its visual range
     #[inspector(min = 0., max = 1., speed = 0.01)]
-    alignment: f32,
+    pub alignment: f32,
+    /// How alignment influence falls off with distance inside the visual radius
+    pub alignment_kernel: InfluenceKernel,
+    /// How cohesion influence falls off with distance inside the visual radius
+    pub cohesion_kernel: InfluenceKernel,
+    /// How separation influence falls off with distance inside the separation radius
+    pub separation_kernel: InfluenceKernel,
     /// Max boids speed
     #[inspector(min = 0., max = 1000., speed = 10.)]
-    max_speed: f32,
-    /// Min boids speed
+    pub max_speed: f32,
+    /// Max steering force each flocking rule can apply
     #[inspector(min = 0., max = 1000., speed = 10.)]
-    max_force: f32,
+    pub max_force: f32,
+    /// Min boids speed, boids are never slower than this so they can't hover in place
+    #[inspector(min = 0., max = 1000., speed = 10.)]
+    pub min_speed: f32,
+    /// Preferred boids speed that the speed regulation force pulls towards
+    #[inspector(min = 0., max = 1000., speed = 10.)]
+    pub cruise_speed: f32,
+    /// How strongly boids return to their cruise speed
+    #[inspector(min = 0., max = 10., speed = 0.01)]
+    pub speed_regulation: f32,
+    /// Max boids turn rate in radians per second, 0 lets boids turn instantly
+    #[inspector(min = 0., max = 100., speed = 0.1)]
+    pub max_turn_rate: f32,
+    /// How quickly the rendered rotation catches up with the heading, 0 snaps instantly
+    #[inspector(min = 0., max = 100., speed = 0.1)]
+    pub rotation_easing: f32,
+    /// How much bigger boids are drawn at max speed, 0 keeps them at their own size
+    #[inspector(min = 0., max = 5., speed = 0.01)]
+    pub speed_scaling: f32,
+    /// Numerical scheme used to move the boids. The steering rules are held for the tick,
+    /// the schemes differ in how they follow the speed regulation, flow field and border
+    pub integrator: Integrator,
+    /// Length of a simulation tick. Steering and integration run once per tick, so a long
+    /// frame runs several ticks rather than one big step
+    #[inspector(min = 0.001, max = 1., speed = 0.001)]
+    pub tick_seconds: f32,
+    /// Most ticks run for a single frame at normal speed, longer frames slow the
+    /// simulation down instead of falling further behind. Sets the max delta of virtual
+    /// time unless the host app has changed it from Bevy's default
+    #[inspector(min = 1, max = 64)]
+    pub max_ticks_per_frame: u32,
+    /// How strongly flow fields (wind, vortices, noise) push the boids
+    #[inspector(min = 0., max = 10., speed = 0.01)]
+    pub flow_weight: f32,
+    /// Draw arrows showing the flow field
+    pub show_flow_field: bool,
+    /// Per-boid quantity the boids are coloured by
+    pub colour_mode: ColourMode,
+    /// Colours the selected quantity is mapped through, from low to high
+    pub colour_ramp: ColourRamp,
+    /// Value shown at the top of the colour ramp in the density, neighbour count and
+    /// separation pressure colour modes
+    #[inspector(min = 0.01, max = 1000., speed = 0.1)]
+    pub colour_scale: f32,
+    /// Colour the trails the same way as the boids
+    pub colour_trails: bool,
+    /// Gizmo overlay showing what boids perceive and the forces acting on them
+    pub debug: DebugOverlay,
+    /// Seed of the simulation random number generator, used when the simulation starts
+    pub seed: u64,
+    /// Random heading change each tick, in radians per square root second
+    #[inspector(min = 0., max = 10., speed = 0.01)]
+    pub angular_noise: f32,
+    /// Strength of the random turning that drifts smoothly over time
+    #[inspector(min = 0., max = 10., speed = 0.01)]
+    pub wander_noise: f32,
+    /// How quickly the random turning drifts back to going straight
+    #[inspector(min = 0., max = 100., speed = 0.1)]
+    pub wander_reversion: f32,
+    /// Give each boid its own weights and max speed, drawn from the distributions below on spawn
+    pub individual_boids: bool,
+    /// Distribution of the per-boid cohesion weight
+    pub cohesion_distribution: SpawnDistribution,
+    /// Distribution of the per-boid separation weight
+    pub separation_distribution: SpawnDistribution,
+    /// Distribution of the per-boid alignment weight
+    pub alignment_distribution: SpawnDistribution,
+    /// Distribution of the per-boid max speed
+    pub max_speed_distribution: SpawnDistribution,
+}
+
+/// Normal distribution a per-boid value is drawn from when the boid is spawned
+#[derive(Reflect, InspectorOptions, Clone, Copy)]
+#[reflect(InspectorOptions)]
+pub struct SpawnDistribution {
+    /// Average value
+    #[inspector(min = 0., max = 1000., speed = 0.01)]
+    pub mean: f32,
+    /// Variance around the average, 0 gives every boid the mean
+    #[inspector(min = 0., max = 10000., speed = 0.01)]
+    pub variance: f32,
+}
+
+impl SpawnDistribution {
+    pub fn new(mean: f32, variance: f32) -> Self {
+        Self { mean, variance }
+    }
+
+    /// Draw a value from the distribution, clamped to be non-negative
+    pub fn sample(&self, rng: &mut impl Rng) -> f32 {
+        (self.mean + standard_normal(rng) * self.variance.sqrt()).max(0.0)
+    }
 }
 
 impl Default for Settings {
     fn default() -> Self {
         Self {
+            paused: false,
+            step: false,
+            time_scale: 1.0,
+            model: FlockingModel::Reynolds,
+            vicsek_noise: 0.5,
+            couzin_orientation_radius: 40.0,
             visual_radius: 50.0,
             separation_radius: 30.0,
             cohesion: 0.6,
             separation: 0.8,
             alignment: 0.6,
+            alignment_kernel: InfluenceKernel::Constant,
+            cohesion_kernel: InfluenceKernel::Constant,
+            separation_kernel: InfluenceKernel::Constant,
             max_speed: 100.0,
             max_force: 50.0,
+            min_speed: 20.0,
+            cruise_speed: 80.0,
+            speed_regulation: 0.5,
+            max_turn_rate: 6.0,
+            rotation_easing: 15.0,
+            speed_scaling: 0.0,
+            integrator: Integrator::default(),
+            tick_seconds: 1.0 / 60.0,
+            max_ticks_per_frame: 8,
+            flow_weight: 1.0,
+            show_flow_field: false,
+            colour_mode: ColourMode::Palette,
+            colour_ramp: ColourRamp::default(),
+            colour_scale: 20.0,
+            colour_trails: false,
+            debug: DebugOverlay::default(),
+            seed: 0,
+            angular_noise: 0.0,
+            wander_noise: 0.0,
+            wander_reversion: 2.0,
+            individual_boids: false,
+            cohesion_distribution: SpawnDistribution::new(0.6, 0.01),
+            separation_distribution: SpawnDistribution::new(0.8, 0.01),
+            alignment_distribution: SpawnDistribution::new(0.6, 0.01),
+            max_speed_distribution: SpawnDistribution::new(100.0, 225.0),
         }
     }
 }
 
 impl Plugin for BoidPlugin {
     fn build(&self, app: &mut App) {
+        app.insert_resource(self.clone());
+        app.init_resource::<Settings>();
+        app.init_resource::<SimulationRng>();
+        app.init_resource::<SimulationBounds>();
+        app.register_type::<Settings>()
+            .register_type::<Boid>()
+            .register_type::<Velocity>()
+            .register_type::<MaxVelocity>()
+            .register_type::<MaxTurnRate>()
+            .register_type::<Acceleration>()
+            .register_type::<Mass>()
+            .register_type::<HeadingNoise>()
+            .register_type::<BoidParameters>()
+            .register_type::<FlockForces>();
+        if self.spawn_player && !app.is_plugin_added::<PlayerPlugin>() {
+            app.add_plugins(PlayerPlugin);
+        }
+        if !app.is_plugin_added::<TrailPlugin>() {
+            app.add_plugins(TrailPlugin);
+        }
+        app.add_plugins((
+            CollectiveModelsPlugin,
+            SteeringPlugin,
+            PathPlugin,
+            FlowFieldPlugin,
+            NumericalDiagnosticsPlugin,
+            EmitterPlugin,
+            FormationPlugin,
+            AppearancePlugin,
+            DebugPlugin,
+        ));
+        // TODO Replace with a spatial hash grid
+        let index = AutomaticUpdate::<Boid>::new()
+            .with_schedule(FixedUpdate)
+            .with_set(SimulationSet::IndexRebuild)
+            .with_spatial_ds(SpatialStructure::KDTree2)
+            .with_frequency(Duration::from_millis(100));
+        // The host app may already keep its own index of the boids
+        if !is_plugin_added(app, &index) && !app.is_plugin_added::<AutomaticUpdate<Boid>>() {
+            app.add_plugins(index);
+        }
         app.add_systems(Startup, spawn_boids);
-        app.add_systems(Update, boid_update);
-        app.add_systems(Update, boid_flock);
+        app.add_systems(PreUpdate, apply_tick_rate);
+        app.add_systems(
+            FixedUpdate,
+            update_simulation_bounds.in_set(SimulationSet::Sense),
+        );
+        app.add_systems(
+            Update,
+            attach_missing_visuals.in_set(SimulationSet::RenderSync),
+        );
+        configure_sets(app);
+        app.add_systems(FixedUpdate, boid_update.in_set(SimulationSet::Integrate));
+        app.add_systems(
+            FixedUpdate,
+            boid_flock
+                .in_set(SteerSet::Rules)
+                .run_if(model_is(FlockingModel::Reynolds)),
+        );
+        app.add_systems(FixedUpdate, perturb_headings.in_set(SteerSet::Noise));
     }
 }
 
-#[derive(Component, Clone)]
+#[derive(Component, Reflect, Clone)]
+#[reflect(Component)]
 pub struct Boid {
-    // How many boids does this boid count for?
-    pub(crate) weight: f32,
+    /// How many boids does this boid count for?
+    pub weight: f32,
 }
 
-/// Spawn BOID_COUNT amount of boids
-pub fn spawn_boids(
-    mut commands: Commands,
-    window_query: Query<&Window, With<PrimaryWindow>>,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<ColorMaterial>>,
-    settings: Res<Settings>,
-) {
-    let window = window_query.get_single().expect("No window found");
-    for _ in 0..BOID_COUNT {
-        let x = random::<f32>() * window.width();
-        let y = random::<f32>() * window.height();
-        let transform = Transform::from_xyz(x, y, 0.0);
-        let velocity = Velocity(
-            Vec2::new(random::<f32>() - 0.5, random::<f32>() - 0.5).normalize()
-                * settings.max_speed,
-        );
+/// Random turning rate of a boid, drifts over time as an Ornstein-Uhlenbeck process
+#[derive(Component, Reflect, Clone, Default)]
+#[reflect(Component)]
+pub struct HeadingNoise {
+    /// Current random turning rate in radians per second
+    pub turn_rate: f32,
+}
+
+/// Per-boid flocking weights and max speed, overriding the global values in `Settings`
+#[derive(Component, Reflect, Clone, Copy)]
+#[reflect(Component)]
+pub struct BoidParameters {
+    pub cohesion: f32,
+    pub separation: f32,
+    pub alignment: f32,
+    pub max_speed: f32,
+}
+
+impl BoidParameters {
+    /// Use the global values from `Settings`
+    pub fn from_settings(settings: &Settings) -> Self {
+        Self {
+            cohesion: settings.cohesion,
+            separation: settings.separation,
+            alignment: settings.alignment,
+            max_speed: settings.max_speed,
+        }
+    }
+
+    /// Draw each value from its spawn distribution in `Settings`
+    pub fn sample(settings: &Settings, rng: &mut impl Rng) -> Self {
+        Self {
+            cohesion: settings.cohesion_distribution.sample(rng),
+            separation: settings.separation_distribution.sample(rng),
+            alignment: settings.alignment_distribution.sample(rng),
+            max_speed: settings.max_speed_distribution.sample(rng),
+        }
+    }
+}
+
+/// Spawns boids with the mesh, colours and trail configured in the BoidPlugin.
+/// All randomness comes from the SimulationRng, so the same seed gives the same boids
+#[derive(SystemParam)]
+pub struct BoidSpawner<'w, 's> {
+    commands: Commands<'w, 's>,
+    meshes: ResMut<'w, Assets<Mesh>>,
+    materials: ResMut<'w, Assets<ColorMaterial>>,
+    boid_assets: Res<'w, BoidAssets>,
+    rng: ResMut<'w, SimulationRng>,
+    config: Res<'w, BoidPlugin>,
+    settings: Res<'w, Settings>,
+}
+
+impl BoidSpawner<'_, '_> {
+    /// The simulation random number generator
+    pub fn rng(&mut self) -> &mut StdRng {
+        &mut self.rng.0
+    }
+
+    /// Spawn a single boid and its trail at `position`, with a velocity sampled relative to
+    /// `centre`, and return the boid entity
+    pub fn spawn(&mut self, position: Vec2, velocity: &SpawnVelocity, centre: Vec2) -> Entity {
+        let rng = &mut self.rng.0;
+        let size = BoidSize(self.config.size.sample(rng));
+        let transform = Transform::from_translation(position.extend(0.0))
+            .with_scale(Vec2::splat(size.0).extend(1.0));
+        let parameters = self
+            .settings
+            .individual_boids
+            .then(|| BoidParameters::sample(&self.settings, rng));
+        let max_speed = parameters.map_or(self.settings.max_speed, |p| p.max_speed);
+        let velocity = Velocity(velocity.sample(position, centre, max_speed, rng));
         let acceleration = Acceleration(Vec2::ZERO);
-        let mesh = Mesh2dHandle(meshes.add(Triangle2d::new(
-            Vec2::Y * 4.0,
-            Vec2::new(-3.5, -4.0),
-            Vec2::new(3.5, -4.0),
-        )));
-        let material = materials.add(Color::srgb_u8(255, 221, 0));
-        let boid = commands
-            .spawn((
-                MaterialMesh2dBundle {
-                    mesh,
-                    material,
-                    transform,
-                    ..default()
-                },
-                Boid { weight: 1. },
-                velocity,
-                acceleration,
-            ))
-            .id();
+        let tint = match self.boid_assets.palette.len() {
+            0 | 1 => Tint(0),
+            colours => Tint(rng.gen_range(0..colours)),
+        };
+        let mut boid = self.commands.spawn((
+            Boid { weight: 1. },
+            tint,
+            size,
+            velocity,
+            acceleration,
+            HeadingNoise::default(),
+        ));
+        if let Some(parameters) = parameters {
+            boid.insert(parameters);
+        }
+        if self.config.record_forces {
+            boid.insert(FlockForces::default());
+        }
+        let boid = boid.id();
+        self.attach_visuals(boid, transform, tint, size);
+        boid
+    }
 
+    /// Give a boid the shared mesh and material of its tint and a trail
+    pub fn attach_visuals(
+        &mut self,
+        boid: Entity,
+        transform: Transform,
+        tint: Tint,
+        size: BoidSize,
+    ) {
+        self.commands.entity(boid).insert(MaterialMesh2dBundle {
+            mesh: self.boid_assets.mesh.clone(),
+            material: self.boid_assets.material(tint.0),
+            transform,
+            ..default()
+        });
+        let depth = -2.0 - self.rng.0.gen::<f32>() * 100.0;
         TrailBuilder::new(boid, transform.translation.xy())
-            .with_local_offset(Vec2::new(0.0, -4.0))
-            .with_colour(TrailColour::gradient(
-                Color::srgba_u8(255, 55, 0, 255),
-                Color::srgba_u8(255, 0, 0, 0),
-            ))
+            .with_local_offset(self.boid_assets.tail * size.0)
+            .with_colour(self.config.trail_colour.clone())
             .with_segments(100)
-            .with_thickness(2.5)
-            .with_depth(-2.0 - random::<f32>() * 100.0)
-            .build(&mut commands, &mut materials, &mut meshes);
+            .with_thickness(2.5 * size.0)
+            .with_depth(depth)
+            .build(&mut self.commands, &mut self.materials, &mut self.meshes);
+    }
+}
+
+/// Whether a plugin of the same type as `plugin` has been added, for plugin types that are
+/// awkward to name
+fn is_plugin_added<P: Plugin>(app: &App, _plugin: &P) -> bool {
+    app.is_plugin_added::<P>()
+}
+
+/// Max delta of virtual time until something changes it
+const DEFAULT_MAX_DELTA: Duration = Duration::from_millis(250);
+
+/// Set the length of the FixedUpdate tick and the most time a frame can add from the Settings.
+/// A max delta set by the host app is left alone
+fn apply_tick_rate(
+    settings: Res<Settings>,
+    mut fixed_time: ResMut<Time<Fixed>>,
+    mut virtual_time: ResMut<Time<Virtual>>,
+    mut applied_max_delta: Local<Option<Duration>>,
+) {
+    if !settings.is_changed() {
+        return;
+    }
+    // Bevy panics on a zero timestep or max delta
+    let tick = Duration::from_secs_f32(settings.tick_seconds.clamp(0.001, 1.0));
+    if fixed_time.timestep() != tick {
+        fixed_time.set_timestep(tick);
+    }
+    if virtual_time.max_delta() != applied_max_delta.unwrap_or(DEFAULT_MAX_DELTA) {
+        return;
+    }
+    let max_delta = tick * settings.max_ticks_per_frame.max(1);
+    virtual_time.set_max_delta(max_delta);
+    *applied_max_delta = Some(max_delta);
+}
+
+/// Give boids without a mesh, such as boids loaded from a scene, the visuals of spawned boids
+pub fn attach_missing_visuals(
+    query: Query<
+        (Entity, &Transform, &Tint, &BoidSize),
+        (With<Boid>, Without<Player>, Without<Mesh2dHandle>),
+    >,
+    mut spawner: BoidSpawner,
+) {
+    for (entity, transform, tint, size) in query.iter() {
+        spawner.attach_visuals(entity, *transform, *tint, *size);
     }
 }
 
-/// Update the boids position, velocity and rotation
+/// Spawn the initial flock configured in the BoidPlugin
+pub fn spawn_boids(window_query: Query<&Window, With<PrimaryWindow>>, mut spawner: BoidSpawner) {
+    let config = spawner.config.clone();
+    let spawn_area = config.spawn_area.unwrap_or_else(|| {
+        let window = window_query.get_single().expect("No window found");
+        Rect::new(0.0, 0.0, window.width(), window.height())
+    });
+    let positions =
+        config
+            .spawn_pattern
+            .positions(spawn_area, config.boid_count, &mut spawner.rng.0);
+    for position in positions {
+        spawner.spawn(position, &config.spawn_velocity, spawn_area.center());
+    }
+}
+
+/// Update the position, velocity and rotation of every entity with a Velocity and Acceleration.
+/// Flocking boids also get their `StateForces`, worked out at every stage of the integrator
+#[allow(clippy::too_many_arguments)]
 pub fn boid_update(
     mut boid_query: Query<(
         &mut Transform,
         &mut Velocity,
         &Acceleration,
-        &Boid,
+        Option<&Mass>,
         Option<&MaxVelocity>,
+        Option<&MaxTurnRate>,
+        Option<&BoidParameters>,
+        Has<Boid>,
+        Has<Player>,
+        Has<Steering>,
     )>,
+    flow_field: Res<FlowField>,
+    bounds: Res<SimulationBounds>,
+    settings: Res<Settings>,
     time: Res<Time>,
 ) {
-    for (mut transform, mut velocity, acceleration, _, max_velocity) in boid_query.iter_mut() {
-        // Update position and velocity
-        transform.translation += Vec3::new(velocity.0.x, velocity.0.y, 0.0) * time.delta_seconds();
-        velocity.0 += acceleration.0 * time.delta_seconds();
+    // Runs in FixedUpdate, so this is the length of a single tick
+    let dt = time.delta_seconds();
+
+    for (
+        mut transform,
+        mut velocity,
+        force,
+        mass,
+        max_velocity,
+        max_turn_rate,
+        parameters,
+        is_boid,
+        is_player,
+        is_steered,
+    ) in boid_query.iter_mut()
+    {
+        let inverse_mass = Mass::inverse(mass);
+        let is_flocking = is_boid && !is_player;
+        let max_turn_rate = match max_turn_rate {
+            Some(max_turn_rate) => Some(max_turn_rate.0),
+            None if is_flocking && settings.max_turn_rate > 0.0 => Some(settings.max_turn_rate),
+            None => None,
+        };
+        let state_forces = is_flocking.then(|| StateForces {
+            settings: &settings,
+            flow_field: &flow_field,
+            // Steered boids go where they are told, Vicsek boids turn away from the border
+            // themselves
+            bounds: bounds
+                .0
+                .filter(|_| !is_steered && settings.model != FlockingModel::Vicsek),
+            max_speed: resolve_max_speed(max_velocity, parameters, &settings),
+        });
+        let facing = (transform.rotation * Vec3::Y).truncate();
+        // The Acceleration from the steering rules is held for the whole tick
+        let held = force.0;
+        let previous_velocity = velocity.0;
+        let position = transform.translation.truncate();
+        let (position, new_velocity) =
+            settings
+                .integrator
+                .step(position, velocity.0, dt, |position, velocity| {
+                    let state = state_forces
+                        .as_ref()
+                        .map_or(Vec2::ZERO, |forces| forces.at(position, velocity));
+                    (held + state) * inverse_mass
+                });
+        velocity.0 = new_velocity;
+
+        // Stop the heading from swinging round faster than the max turn rate
+        if let Some(max_turn_rate) = max_turn_rate {
+            velocity.0 = limit_turn(previous_velocity, velocity.0, max_turn_rate * dt);
+        }
 
         // Limit velocity to max velocity if specified
         if let Some(max_velocity) = max_velocity {
             limit_vec(&mut velocity.0, max_velocity.0);
         }
 
-        let direction = Vec2::new(velocity.0.x, velocity.0.y).normalize();
-        if direction.length() > 0.0 {
-            // Update rotation to facing direction
-            transform.rotation = Quat::from_rotation_z(-direction.x.atan2(direction.y));
+        // Keep boids above the min speed, stalled boids carry on in the direction they face
+        if is_flocking && velocity.0.length() < settings.min_speed {
+            let heading = velocity.0.try_normalize().unwrap_or(facing);
+            velocity.0 = heading * settings.min_speed;
+        }
+        transform.translation.x = position.x;
+        transform.translation.y = position.y;
+
+        // Keep the previous rotation if stationary, normalizing a zero velocity gives NaN
+        if let Some(direction) = velocity.0.try_normalize() {
+            // Ease rotation towards the facing direction
+            let target = Quat::from_rotation_z(-direction.x.atan2(direction.y));
+            transform.rotation = if settings.rotation_easing > 0.0 {
+                let t = 1.0 - (-settings.rotation_easing * dt).exp();
+                transform.rotation.slerp(target, t)
+            } else {
+                target
+            };
         }
     }
 }
 
+/// Randomly perturb boid headings after the flocking forces, using the simulation RNG.
+/// Adds independent angular jitter each tick plus a smoothly drifting turn rate
+pub fn perturb_headings(
+    mut boid_query: Query<(&mut Velocity, &mut HeadingNoise), (With<Boid>, Without<Player>)>,
+    mut rng: ResMut<SimulationRng>,
+    settings: Res<Settings>,
+    time: Res<Time>,
+) {
+    if settings.angular_noise == 0.0 && settings.wander_noise == 0.0 {
+        return;
+    }
+    let dt = time.delta_seconds();
+    for (mut velocity, mut noise) in boid_query.iter_mut() {
+        // Ornstein-Uhlenbeck: decay towards 0 and diffuse randomly
+        noise.turn_rate += -settings.wander_reversion * noise.turn_rate * dt
+            + settings.wander_noise * dt.sqrt() * standard_normal(&mut rng.0);
+
+        let jitter = settings.angular_noise * dt.sqrt() * standard_normal(&mut rng.0);
+        let angle = noise.turn_rate * dt + jitter;
+        velocity.0 = Vec2::from_angle(angle).rotate(velocity.0);
+    }
+}
+
+/// Forces on a flocking boid that depend on its position and velocity: speed regulation,
+/// the flow field and the border. `boid_update` works them out at every stage of the
+/// integrator on top of the Acceleration, so higher order integrators see them change
+/// during the tick
+pub struct StateForces<'a> {
+    pub settings: &'a Settings,
+    pub flow_field: &'a FlowField,
+    /// Area the border force keeps the boid inside, None for no border force
+    pub bounds: Option<Rect>,
+    /// Max speed of the boid, caps its cruise speed
+    pub max_speed: f32,
+}
+
+impl StateForces<'_> {
+    /// Sum of the forces at a position and velocity
+    pub fn at(&self, position: Vec2, velocity: Vec2) -> Vec2 {
+        let settings = self.settings;
+        let cruise_speed = settings
+            .cruise_speed
+            .min(self.max_speed)
+            .max(settings.min_speed);
+        let mut force = speed_regulation(velocity, cruise_speed, settings.speed_regulation);
+        if settings.flow_weight != 0.0 {
+            force += self.flow_field.sample(position) * settings.flow_weight;
+        }
+        if let Some(bounds) = self.bounds {
+            force += border_adjustment(position, bounds, settings);
+        }
+        force
+    }
+}
+
+/// Push a boid back towards its cruise speed along its heading
+fn speed_regulation(velocity: Vec2, cruise_speed: f32, strength: f32) -> Vec2 {
+    let Some(heading) = velocity.try_normalize() else {
+        return Vec2::ZERO;
+    };
+    heading * (cruise_speed - velocity.length()) * strength
+}
+
+/// Track what the camera shows of the window, so the border follows the camera
+fn update_simulation_bounds(
+    window_query: Query<&Window, With<PrimaryWindow>>,
+    camera_query: Query<&Transform, With<Camera>>,
+    mut bounds: ResMut<SimulationBounds>,
+) {
+    // Headless apps have no camera or window to keep the boids inside
+    let view = camera_query
+        .get_single()
+        .ok()
+        .zip(window_query.get_single().ok());
+    bounds.0 = view.map(|(camera_transform, window)| {
+        Rect::from_center_size(camera_transform.translation.truncate(), window.size())
+    });
+}
+
 /// Flock the boids by following the alignment, cohesion and separation rules
 /// Keeping here to profile against newer methods of flocking
 #[allow(dead_code)]
 pub fn boid_flock_old(
-    window_query: Query<&Window, With<PrimaryWindow>>,
-    camera_query: Query<&Transform, With<Camera>>,
+    bounds: Res<SimulationBounds>,
     mut boid_query: Query<(
         &mut Acceleration,
         &Transform,
         &Velocity,
         &Boid,
         Option<&Player>,
+        Option<&BoidParameters>,
     )>,
     settings: Res<Settings>,
 ) {
     let boid_data: Vec<(Transform, Velocity, Boid)> = boid_query
         .iter()
-        .map(|(_, t, v, b, _)| (t.clone(), v.clone(), b.clone()))
+        .map(|(_, t, v, b, _, _)| (t.clone(), v.clone(), b.clone()))
         .collect();
-    let camera_transform = camera_query.get_single().expect("No camera found");
-    let window = window_query.get_single().expect("No window found");
-
-    for (mut acceleration, transform, velocity, _, player) in boid_query.iter_mut() {
+    for (mut acceleration, transform, velocity, _, player, parameters) in boid_query.iter_mut() {
         // we don't want to change the player force, but we want to incorporate it into the flocking
         // behaviour
         if player.is_some() {
             continue;
         }
 
-        let mut alignment = Vec2::ZERO;
-        let mut cohesion = Vec2::ZERO;
-        let mut separation = Vec2::ZERO;
-        let mut count = 0;
-        let mut separation_count = 0;
         let position: Vec2 = transform.translation.truncate();
 
         // Iterate through every boid and calculate the alignment, cohesion and separation
+        let mut neighbourhood = Neighbourhood::default();
         for (other_transform, other_velocity, boid) in boid_data.iter() {
-            let other_position: Vec2 = other_transform.translation.truncate();
-            let weight = boid.weight;
-            if position == other_position {
-                continue;
-            }
-            let distance = position.distance(other_position);
-            if distance < settings.visual_radius {
-                alignment += other_velocity.0 * weight;
-                cohesion += other_position * weight;
-
-                count += 1;
-            }
-
-            if distance < settings.separation_radius {
-                let mut diff = position - other_position;
-                diff /= distance.max(0.000001);
-                separation += diff * weight;
-                separation_count += 1;
-            }
+            neighbourhood.add(
+                position,
+                other_transform.translation.truncate(),
+                other_velocity.0,
+                boid.weight,
+                &settings,
+            );
         }
 
-        // Exit out if there are no boids within the radius, this will just keep the current velocity
-        if count > 0 {
-            // Set the alignment to a direction multiplied by max speed so we are always travelling at
-            // max speed. This can be removed if you want the average velocity
-            alignment /= count as f32;
-            alignment = alignment.normalize() * settings.max_speed;
-            alignment -= velocity.0;
-            limit_vec(&mut alignment, settings.max_force);
-            alignment *= settings.alignment;
-
-            // Cohesion is the average position of all the boids within the set radius
-            // Add the velocity to move towards the average position
-            cohesion /= count as f32;
-            cohesion -= position;
-            cohesion = cohesion.normalize() * settings.max_speed;
-            cohesion -= velocity.0;
-            limit_vec(&mut cohesion, settings.max_force);
-            cohesion *= settings.cohesion;
-        }
-
-        if separation_count > 0 {
-            // separation is a force in the direction away from all neighbouring boids
-            separation /= separation_count as f32;
-            separation = separation.normalize() * settings.max_speed;
-            separation -= velocity.0;
-            limit_vec(&mut separation, settings.max_force);
-            separation *= settings.separation;
-        }
-
-        // Check window bounds and apply force inwards if we are outside of them
-        const MARGIN: f32 = -10.0;
-        let min_x = camera_transform.translation.x - window.width() / 2.0 - MARGIN;
-        let max_x = camera_transform.translation.x + window.width() / 2.0 + MARGIN;
-        let min_y = camera_transform.translation.y - window.height() / 2.0 - MARGIN;
-        let max_y = camera_transform.translation.y + window.height() / 2.0 + MARGIN;
-        let mut border_adjustment = Vec2::new(
-            if transform.translation.x < min_x {
-                settings.max_speed
-            } else {
-                0.0
-            } + if transform.translation.x > max_x {
-                -settings.max_speed
-            } else {
-                0.0
-            },
-            if transform.translation.y < min_y {
-                settings.max_speed
-            } else {
-                0.0
-            } + if transform.translation.y > max_y {
-                -settings.max_speed
-            } else {
-                0.0
-            },
+        let parameters = parameters
+            .copied()
+            .unwrap_or_else(|| BoidParameters::from_settings(&settings));
+        let forces = FlockForces::new(
+            &neighbourhood,
+            position,
+            velocity.0,
+            &parameters,
+            bounds.0,
+            &settings,
         );
-        border_adjustment *= 2.0;
-
-        *acceleration = Acceleration(alignment + cohesion + separation + border_adjustment);
+        *acceleration = Acceleration(forces.rules());
     }
 }
 
-/// Flock the boids by following the alignment, cohesion and separation rules
+/// Flock the boids by following the alignment, cohesion and separation rules.
+/// Boids with a FlockForces component get the individual forces written to it
 pub fn boid_flock(
     treeaccess: Res<KDTree2<Boid>>,
-    window_query: Query<&Window, With<PrimaryWindow>>,
-    camera_query: Query<&Transform, With<Camera>>,
-    mut boid_query: Query<(
-        &mut Acceleration,
-        &Transform,
-        &Velocity,
-        &Boid,
-        Option<&Player>,
-    )>,
+    bounds: Res<SimulationBounds>,
+    mut boid_query: Query<
+        (
+            &mut Acceleration,
+            &Transform,
+            &Velocity,
+            &Boid,
+            Option<&Player>,
+            Option<&BoidParameters>,
+            Option<&mut FlockForces>,
+        ),
+        Without<Steering>,
+    >,
     neighbour_query: Query<(&Transform, &Velocity, &Boid)>,
     settings: Res<Settings>,
 ) {
-    let camera_transform = camera_query.get_single().expect("No camera found");
-    let window = window_query.get_single().expect("No window found");
-
-    for (mut acceleration, transform, velocity, _, player) in boid_query.iter_mut() {
+    for (mut acceleration, transform, velocity, _, player, parameters, recorded) in
+        boid_query.iter_mut()
+    {
         // we don't want to change the player force, but we want to incorporate it into the flocking
         // behaviour
         if player.is_some() {
             continue;
         }
 
-        let mut alignment = Vec2::ZERO;
-        let mut cohesion = Vec2::ZERO;
-        let mut separation = Vec2::ZERO;
-        let mut count = 0;
-        let mut separation_count = 0;
         let position: Vec2 = transform.translation.truncate();
+        let neighbourhood =
+            Neighbourhood::gather(position, &treeaccess, &neighbour_query, &settings);
 
-        for (_, entity) in treeaccess.within_distance(position, settings.visual_radius) {
-            if let Ok((other_transform, other_velocity, boid)) =
-                neighbour_query.get(entity.unwrap())
-            {
-                let other_position: Vec2 = other_transform.translation.truncate();
-                let weight = boid.weight;
-                if position == other_position {
-                    continue;
-                }
-                let distance = position.distance(other_position);
-                if distance < settings.visual_radius {
-                    alignment += other_velocity.0 * weight;
-                    cohesion += other_position * weight;
-
-                    count += 1;
-                }
-
-                if distance < settings.separation_radius {
-                    let mut diff = position - other_position;
-                    diff /= distance.max(0.000001);
-                    separation += diff * weight;
-                    separation_count += 1;
-                }
-            }
+        let parameters = parameters
+            .copied()
+            .unwrap_or_else(|| BoidParameters::from_settings(&settings));
+        let forces = FlockForces::new(
+            &neighbourhood,
+            position,
+            velocity.0,
+            &parameters,
+            bounds.0,
+            &settings,
+        );
+        *acceleration = Acceleration(forces.rules());
+        if let Some(mut recorded) = recorded {
+            *recorded = forces;
         }
+    }
+}
 
-        // Exit out if there are no boids within the radius, this will just keep the current velocity
-        if count > 0 {
-            // Set the alignment to a direction multiplied by max speed so we are always travelling at
-            // max speed. This can be removed if you want the average velocity
-            alignment /= count as f32;
-            alignment = alignment.normalize() * settings.max_speed;
-            alignment -= velocity.0;
-            limit_vec(&mut alignment, settings.max_force);
-            alignment *= settings.alignment;
-
-            // Cohesion is the average position of all the boids within the set radius
-            // Add the velocity to move towards the average position
-            cohesion /= count as f32;
-            cohesion -= position;
-            cohesion = cohesion.normalize() * settings.max_speed;
-            cohesion -= velocity.0;
-            limit_vec(&mut cohesion, settings.max_force);
-            cohesion *= settings.cohesion;
-        }
+/// The forces that make up the Reynolds flocking force. As a component it holds the
+/// forces of the boid from the last time it flocked, add it to the boids to inspect
+#[derive(Component, Reflect, Clone, Copy, Default, Debug, PartialEq)]
+#[reflect(Component)]
+pub struct FlockForces {
+    pub alignment: Vec2,
+    pub cohesion: Vec2,
+    pub separation: Vec2,
+    /// Border force at the start of the tick, applied by `boid_update` as one of the
+    /// `StateForces` rather than through Acceleration
+    pub border: Vec2,
+    /// Number of other boids within the visual radius
+    pub neighbours: u32,
+}
 
-        if separation_count > 0 {
-            // separation is a force in the direction away from all neighbouring boids
-            separation /= separation_count as f32;
-            separation = separation.normalize() * settings.max_speed;
-            separation -= velocity.0;
-            limit_vec(&mut separation, settings.max_force);
-            separation *= settings.separation;
+impl FlockForces {
+    /// Apply the alignment, cohesion and separation rules and the border adjustment.
+    /// Without `bounds` there is no border force
+    pub fn new(
+        neighbourhood: &Neighbourhood,
+        position: Vec2,
+        velocity: Vec2,
+        parameters: &BoidParameters,
+        bounds: Option<Rect>,
+        settings: &Settings,
+    ) -> Self {
+        let max_speed = parameters.max_speed;
+        let max_force = settings.max_force;
+        // If there are no boids within the radius these are zero, this will just keep the current velocity
+        Self {
+            alignment: neighbourhood.alignment(velocity, max_speed, max_force)
+                * parameters.alignment,
+            cohesion: neighbourhood.cohesion(position, velocity, max_speed, max_force)
+                * parameters.cohesion,
+            separation: neighbourhood.separation(velocity, max_speed, max_force)
+                * parameters.separation,
+            border: bounds.map_or(Vec2::ZERO, |bounds| {
+                border_adjustment(position, bounds, settings)
+            }),
+            neighbours: neighbourhood.count,
         }
+    }
 
-        // Check window bounds and apply force inwards if we are outside of them
-        const MARGIN: f32 = -10.0;
-        let min_x = camera_transform.translation.x - window.width() / 2.0 - MARGIN;
-        let max_x = camera_transform.translation.x + window.width() / 2.0 + MARGIN;
-        let min_y = camera_transform.translation.y - window.height() / 2.0 - MARGIN;
-        let max_y = camera_transform.translation.y + window.height() / 2.0 + MARGIN;
-        let mut border_adjustment = Vec2::new(
-            if transform.translation.x < min_x {
-                settings.max_speed
-            } else {
-                0.0
-            } + if transform.translation.x > max_x {
-                -settings.max_speed
-            } else {
-                0.0
-            },
-            if transform.translation.y < min_y {
-                settings.max_speed
-            } else {
-                0.0
-            } + if transform.translation.y > max_y {
-                -settings.max_speed
-            } else {
-                0.0
-            },
-        );
-        border_adjustment *= 2.0;
+    /// The forces a boid recorded while flocking, or if it doesn't record them the forces it
+    /// would flock with now. These are the Reynolds forces whatever `Settings::model` is
+    #[allow(clippy::too_many_arguments)]
+    pub fn recorded_or_compute(
+        recorded: Option<&FlockForces>,
+        position: Vec2,
+        velocity: Vec2,
+        parameters: Option<&BoidParameters>,
+        treeaccess: &KDTree2<Boid>,
+        neighbour_query: &Query<(&Transform, &Velocity, &Boid)>,
+        bounds: Option<Rect>,
+        settings: &Settings,
+    ) -> Self {
+        recorded.copied().unwrap_or_else(|| {
+            let neighbourhood =
+                Neighbourhood::gather(position, treeaccess, neighbour_query, settings);
+            let parameters = parameters
+                .copied()
+                .unwrap_or_else(|| BoidParameters::from_settings(settings));
+            Self::new(
+                &neighbourhood,
+                position,
+                velocity,
+                &parameters,
+                bounds,
+                settings,
+            )
+        })
+    }
+
+    /// Sum of all the forces
+    pub fn total(&self) -> Vec2 {
+        self.rules() + self.border
+    }
 
-        *acceleration = Acceleration(alignment + cohesion + separation + border_adjustment);
+    /// Sum of the alignment, cohesion and separation forces, the Acceleration flocking sets
+    pub fn rules(&self) -> Vec2 {
+        self.alignment + self.cohesion + self.separation
     }
 }
 
+/// Check the simulation bounds and apply force inwards if we are outside of them
+pub(crate) fn border_adjustment(position: Vec2, bounds: Rect, settings: &Settings) -> Vec2 {
+    const MARGIN: f32 = -10.0;
+    let inner = bounds.inflate(MARGIN);
+    let (min_x, min_y, max_x, max_y) = (inner.min.x, inner.min.y, inner.max.x, inner.max.y);
+    let mut border_adjustment = Vec2::new(
+        if position.x < min_x {
+            settings.max_speed
+        } else {
+            0.0
+        } + if position.x > max_x {
+            -settings.max_speed
+        } else {
+            0.0
+        },
+        if position.y < min_y {
+            settings.max_speed
+        } else {
+            0.0
+        } + if position.y > max_y {
+            -settings.max_speed
+        } else {
+            0.0
+        },
+    );
+    border_adjustment *= 2.0;
+    border_adjustment
+}
+
+/// Max speed of an entity, from its MaxVelocity or BoidParameters, falling back to Settings
+pub fn resolve_max_speed(
+    max_velocity: Option<&MaxVelocity>,
+    parameters: Option<&BoidParameters>,
+    settings: &Settings,
+) -> f32 {
+    max_velocity
+        .map(|max| max.0)
+        .or(parameters.map(|p| p.max_speed))
+        .unwrap_or(settings.max_speed)
+}
+
+/// Rotate `velocity` back towards `previous` so the heading turns by at most `max_angle` radians
+pub fn limit_turn(previous: Vec2, velocity: Vec2, max_angle: f32) -> Vec2 {
+    if previous == Vec2::ZERO || velocity == Vec2::ZERO {
+        return velocity;
+    }
+    let angle = previous.angle_between(velocity);
+    if angle.abs() <= max_angle {
+        return velocity;
+    }
+    let heading = Vec2::from_angle(max_angle.copysign(angle)).rotate(previous.normalize());
+    heading * velocity.length()
+}
+
 /// Limit a Vec2's magnitude to max
 pub fn limit_vec(velocity: &mut Vec2, max: f32) {
     let speed = velocity.length();
@@ -435,7 +1102,90 @@ pub fn move_boid_to_player(
 ) {
     let player_position = player_query.get_single().unwrap().1.translation;
     for (mut transform, _) in boid_query.iter_mut() {
-        let direction = (player_position - transform.translation).normalize();
+        let direction = (player_position - transform.translation).normalize_or_zero();
         transform.translation += direction * ENEMY_SPEED * time.delta_seconds();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// App without a window or camera that flocks the boids every update
+    fn headless_app() -> App {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins);
+        app.init_resource::<Settings>();
+        app.init_resource::<SimulationBounds>();
+        app.add_plugins(
+            AutomaticUpdate::<Boid>::new()
+                .with_spatial_ds(SpatialStructure::KDTree2)
+                .with_frequency(Duration::ZERO),
+        );
+        app.add_systems(Update, boid_flock);
+        app
+    }
+
+    fn spawn_boid(app: &mut App, position: Vec2, velocity: Vec2) -> Entity {
+        let transform = Transform::from_translation(position.extend(0.0));
+        app.world_mut()
+            .spawn((
+                transform,
+                GlobalTransform::from(transform),
+                Boid { weight: 1.0 },
+                Velocity(velocity),
+                Acceleration(Vec2::ZERO),
+                FlockForces::default(),
+            ))
+            .id()
+    }
+
+    #[test]
+    fn separation_dominates_when_two_boids_overlap() {
+        let mut app = headless_app();
+        let velocity = Vec2::new(100.0, 0.0);
+        let boid = spawn_boid(&mut app, Vec2::ZERO, velocity);
+        spawn_boid(&mut app, Vec2::new(0.0, 2.0), velocity);
+        // The index is built after the first update
+        for _ in 0..3 {
+            app.update();
+        }
+
+        let forces = *app.world().get::<FlockForces>(boid).unwrap();
+        assert_eq!(forces.neighbours, 1);
+        assert_eq!(forces.border, Vec2::ZERO);
+        assert!(forces.separation.y < 0.0, "{forces:?}");
+        assert!(
+            forces.separation.length() > forces.alignment.length() + forces.cohesion.length(),
+            "{forces:?}"
+        );
+        let acceleration = app.world().get::<Acceleration>(boid).unwrap();
+        assert!(acceleration.0.y < 0.0);
+    }
+
+    #[test]
+    fn speed_regulation_is_integrated_as_it_changes() {
+        let settings = Settings::default();
+        let flow_field = FlowField::default();
+        let forces = StateForces {
+            settings: &settings,
+            flow_field: &flow_field,
+            bounds: None,
+            max_speed: settings.max_speed,
+        };
+        let speed = 10.0;
+        let dt = 0.5;
+        // The regulation decays the gap to the cruise speed exponentially
+        let gap = settings.cruise_speed - speed;
+        let exact = settings.cruise_speed - gap * (-settings.speed_regulation * dt).exp();
+        let error = |integrator: Integrator| {
+            let (_, velocity) =
+                integrator.step(Vec2::ZERO, Vec2::X * speed, dt, |position, velocity| {
+                    forces.at(position, velocity)
+                });
+            (velocity.x - exact).abs()
+        };
+        assert!(error(Integrator::Rk4) < error(Integrator::VelocityVerlet));
+        assert!(error(Integrator::VelocityVerlet) < error(Integrator::ExplicitEuler));
+    }
+}
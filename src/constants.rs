@@ -1,5 +1,4 @@
 pub const PLAYER_FORCE: f32 = 1000.0;
 pub const PLAYER_MAX_TURN_SPEED: f32 = 5.0;
 pub const PLAYER_MAX_SPEED: f32 = 200.0;
-pub const BOID_COUNT: u32 = 1000;
 pub const ENEMY_SPEED: f32 = 100.0;
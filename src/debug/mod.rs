@@ -0,0 +1,242 @@
+use crate::boid::{Boid, BoidParameters, FlockForces, Settings, SimulationBounds, Velocity};
+use crate::models::FlockingModel;
+use crate::player::components::Player;
+use crate::schedule::SimulationSet;
+use bevy::prelude::*;
+use bevy_inspector_egui::prelude::*;
+use bevy_spatial::kdtree::KDTree2;
+
+#[allow(unused_imports)]
+pub mod prelude {
+    pub use super::{DebugOverlay, DebugPlugin, Selected};
+}
+
+pub struct DebugPlugin;
+
+impl Plugin for DebugPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<Selected>();
+        app.add_systems(Update, toggle_debug_overlay);
+        // The run condition skips the drawing systems entirely while the overlay is off
+        app.add_systems(
+            Update,
+            (draw_perception, draw_median_splits)
+                .in_set(SimulationSet::RenderSync)
+                .run_if(debug_overlay_enabled),
+        );
+    }
+}
+
+/// What the debug gizmo overlay draws, toggled with F3
+#[derive(Reflect, InspectorOptions, Clone)]
+#[reflect(InspectorOptions)]
+pub struct DebugOverlay {
+    pub enabled: bool,
+    /// Draw every boid, otherwise only boids marked as `Selected`
+    pub all_boids: bool,
+    /// Visual and separation radius circles
+    pub radii: bool,
+    /// Alignment, cohesion, separation and border force arrows, only drawn with the Reynolds
+    /// model
+    pub forces: bool,
+    pub velocity: bool,
+    /// Median splits of the flock, an approximation of how a KD-tree divides it up
+    pub median_splits: bool,
+    /// Length of the force and velocity arrows per unit of force or speed
+    #[inspector(min = 0., max = 10., speed = 0.01)]
+    pub arrow_scale: f32,
+    /// How many times the flock is split when drawing the median splits
+    #[inspector(min = 1, max = 16)]
+    pub split_depth: u32,
+}
+
+impl Default for DebugOverlay {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            all_boids: true,
+            radii: true,
+            forces: true,
+            velocity: true,
+            median_splits: false,
+            arrow_scale: 0.5,
+            split_depth: 6,
+        }
+    }
+}
+
+/// Marks boids for the debug overlay to draw when it isn't drawing all boids
+#[derive(Component, Reflect, Clone, Copy, Default)]
+#[reflect(Component)]
+pub struct Selected;
+
+const VISUAL_RADIUS_COLOUR: Color = Color::srgba(0.3, 0.6, 1.0, 0.25);
+const SEPARATION_RADIUS_COLOUR: Color = Color::srgba(1.0, 0.3, 0.3, 0.4);
+const VELOCITY_COLOUR: Color = Color::WHITE;
+const ALIGNMENT_COLOUR: Color = Color::srgb(0.2, 0.9, 0.3);
+const COHESION_COLOUR: Color = Color::srgb(0.3, 0.6, 1.0);
+const SEPARATION_COLOUR: Color = Color::srgb(1.0, 0.3, 0.3);
+const BORDER_COLOUR: Color = Color::srgb(1.0, 0.8, 0.2);
+const SPLIT_COLOUR: Color = Color::srgba(0.8, 0.8, 0.8, 0.3);
+
+/// Run condition that is true while the debug overlay is enabled in `Settings`
+pub fn debug_overlay_enabled(settings: Res<Settings>) -> bool {
+    settings.debug.enabled
+}
+
+/// F3 turns the debug overlay on and off
+fn toggle_debug_overlay(keyboard_input: Res<ButtonInput<KeyCode>>, mut settings: ResMut<Settings>) {
+    if keyboard_input.just_pressed(KeyCode::F3) {
+        settings.debug.enabled = !settings.debug.enabled;
+    }
+}
+
+/// Draw the perception radii, flocking forces and velocity of the debugged boids
+#[allow(clippy::too_many_arguments)]
+fn draw_perception(
+    mut gizmos: Gizmos,
+    treeaccess: Res<KDTree2<Boid>>,
+    bounds: Res<SimulationBounds>,
+    boid_query: Query<
+        (
+            &Transform,
+            &Velocity,
+            Option<&BoidParameters>,
+            Option<&FlockForces>,
+            Has<Selected>,
+        ),
+        (With<Boid>, Without<Player>),
+    >,
+    neighbour_query: Query<(&Transform, &Velocity, &Boid)>,
+    settings: Res<Settings>,
+    mut noted_model: Local<Option<FlockingModel>>,
+) {
+    let overlay = &settings.debug;
+    // The other models don't steer with these forces, so drawing them would be misleading
+    let draw_forces = overlay.forces && settings.model == FlockingModel::Reynolds;
+    let other_model = (overlay.forces && !draw_forces).then_some(settings.model);
+    if other_model.is_some() && other_model != *noted_model {
+        info!(
+            "The debug overlay only draws forces for the Reynolds model, the flock uses {:?}",
+            settings.model
+        );
+    }
+    *noted_model = other_model;
+
+    for (transform, velocity, parameters, recorded, selected) in boid_query.iter() {
+        if !overlay.all_boids && !selected {
+            continue;
+        }
+        let position = transform.translation.truncate();
+
+        if overlay.radii {
+            gizmos.circle_2d(position, settings.visual_radius, VISUAL_RADIUS_COLOUR);
+            gizmos.circle_2d(
+                position,
+                settings.separation_radius,
+                SEPARATION_RADIUS_COLOUR,
+            );
+        }
+        if overlay.velocity {
+            let end = position + velocity.0 * overlay.arrow_scale;
+            gizmos.arrow_2d(position, end, VELOCITY_COLOUR);
+        }
+        if draw_forces {
+            let forces = FlockForces::recorded_or_compute(
+                recorded,
+                position,
+                velocity.0,
+                parameters,
+                &treeaccess,
+                &neighbour_query,
+                bounds.0,
+                &settings,
+            );
+            for (force, colour) in [
+                (forces.alignment, ALIGNMENT_COLOUR),
+                (forces.cohesion, COHESION_COLOUR),
+                (forces.separation, SEPARATION_COLOUR),
+                (forces.border, BORDER_COLOUR),
+            ] {
+                if force != Vec2::ZERO {
+                    gizmos.arrow_2d(position, position + force * overlay.arrow_scale, colour);
+                }
+            }
+        }
+    }
+}
+
+/// Approximate the regions a KD-tree splits the flock into, by splitting at the median
+/// position along alternating axes. The splits are worked out from the positions rather than
+/// read from the bevy_spatial tree used for neighbour queries, so they may differ from it
+fn draw_median_splits(
+    mut gizmos: Gizmos,
+    boid_query: Query<&Transform, With<Boid>>,
+    settings: Res<Settings>,
+) {
+    if !settings.debug.median_splits {
+        return;
+    }
+    let mut positions: Vec<Vec2> = boid_query
+        .iter()
+        .map(|transform| transform.translation.truncate())
+        .filter(|position| position.is_finite())
+        .collect();
+    let Some(bounds) = positions
+        .iter()
+        .map(|position| Rect::from_center_size(*position, Vec2::ZERO))
+        .reduce(|a, b| a.union(b))
+    else {
+        return;
+    };
+    gizmos.rect_2d(bounds.center(), 0.0, bounds.size(), SPLIT_COLOUR);
+    draw_median_split(
+        &mut gizmos,
+        &mut positions,
+        bounds,
+        0,
+        settings.debug.split_depth,
+    );
+}
+
+fn draw_median_split(
+    gizmos: &mut Gizmos,
+    positions: &mut [Vec2],
+    bounds: Rect,
+    depth: u32,
+    max_depth: u32,
+) {
+    if depth >= max_depth || positions.len() < 2 {
+        return;
+    }
+    let median = positions.len() / 2;
+    let split_x = depth % 2 == 0;
+    let (left_bounds, right_bounds) = if split_x {
+        positions.select_nth_unstable_by(median, |a, b| a.x.total_cmp(&b.x));
+        let x = positions[median].x;
+        gizmos.line_2d(
+            Vec2::new(x, bounds.min.y),
+            Vec2::new(x, bounds.max.y),
+            SPLIT_COLOUR,
+        );
+        (
+            Rect::from_corners(bounds.min, Vec2::new(x, bounds.max.y)),
+            Rect::from_corners(Vec2::new(x, bounds.min.y), bounds.max),
+        )
+    } else {
+        positions.select_nth_unstable_by(median, |a, b| a.y.total_cmp(&b.y));
+        let y = positions[median].y;
+        gizmos.line_2d(
+            Vec2::new(bounds.min.x, y),
+            Vec2::new(bounds.max.x, y),
+            SPLIT_COLOUR,
+        );
+        (
+            Rect::from_corners(bounds.min, Vec2::new(bounds.max.x, y)),
+            Rect::from_corners(Vec2::new(bounds.min.x, y), bounds.max),
+        )
+    };
+    let (left, right) = positions.split_at_mut(median);
+    draw_median_split(gizmos, left, left_bounds, depth + 1, max_depth);
+    draw_median_split(gizmos, right, right_bounds, depth + 1, max_depth);
+}
@@ -0,0 +1,80 @@
+use crate::boid::{boid_update, Acceleration, Velocity};
+use crate::schedule::SimulationSet;
+use bevy::prelude::*;
+
+pub struct NumericalDiagnosticsPlugin;
+
+impl Plugin for NumericalDiagnosticsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<NumericalHealth>();
+        app.add_systems(
+            FixedUpdate,
+            check_numerical_health
+                .in_set(SimulationSet::Integrate)
+                .after(boid_update),
+        );
+    }
+}
+
+/// Running totals of the non-finite values found and recovered by `check_numerical_health`
+#[derive(Resource, Default)]
+pub struct NumericalHealth {
+    /// Number of times an entity had to be recovered
+    pub recoveries: u32,
+}
+
+/// Find entities with a NaN or infinite position, rotation, velocity or acceleration,
+/// report them and reset the broken values so they don't spread to neighbours and trails
+pub fn check_numerical_health(
+    camera_query: Query<&Transform, With<Camera>>,
+    mut query: Query<
+        (
+            Entity,
+            &mut Transform,
+            &mut Velocity,
+            Option<&mut Acceleration>,
+        ),
+        Without<Camera>,
+    >,
+    mut health: ResMut<NumericalHealth>,
+) {
+    let centre = camera_query
+        .get_single()
+        .map_or(Vec2::ZERO, |t| t.translation.truncate());
+
+    for (entity, mut transform, mut velocity, acceleration) in query.iter_mut() {
+        let mut problems: Vec<&str> = Vec::new();
+
+        if !transform.translation.is_finite() {
+            problems.push("position");
+            transform.translation = centre.extend(transform.translation.z);
+            if !transform.translation.z.is_finite() {
+                transform.translation.z = 0.0;
+            }
+        }
+        if !transform.rotation.is_finite() {
+            problems.push("rotation");
+            transform.rotation = Quat::IDENTITY;
+        }
+        if !velocity.0.is_finite() {
+            problems.push("velocity");
+            velocity.0 = Vec2::ZERO;
+        }
+        if let Some(mut acceleration) = acceleration {
+            if !acceleration.0.is_finite() {
+                problems.push("acceleration");
+                acceleration.0 = Vec2::ZERO;
+            }
+        }
+
+        if !problems.is_empty() {
+            health.recoveries += 1;
+            warn!(
+                "Entity {:?} had non-finite {}, recovered it ({} recoveries so far)",
+                entity,
+                problems.join(", "),
+                health.recoveries
+            );
+        }
+    }
+}
@@ -0,0 +1,141 @@
+use crate::boid::{Boid, BoidSpawner};
+use crate::player::components::Player;
+use crate::schedule::SimulationSet;
+use crate::spawn::{SpawnHeading, SpawnVelocity};
+use bevy::prelude::*;
+use rand::Rng;
+
+#[allow(unused_imports)]
+pub mod prelude {
+    pub use super::{BoidEmitter, BoidSink, EmitterPlugin};
+}
+
+pub struct EmitterPlugin;
+
+impl Plugin for EmitterPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<BoidEmitter>()
+            .register_type::<BoidSink>();
+        app.add_systems(
+            FixedUpdate,
+            (emit_boids, sink_boids).in_set(SimulationSet::Sense),
+        );
+    }
+}
+
+/// Spawns boids over time at the position of its Transform
+#[derive(Component, Reflect, Clone)]
+#[reflect(Component)]
+pub struct BoidEmitter {
+    /// Boids spawned per second
+    pub rate: f32,
+    /// Boids are spawned uniformly inside this radius around the emitter
+    pub radius: f32,
+    /// Initial heading and speed of the spawned boids
+    pub velocity: SpawnVelocity,
+    /// Stop after spawning this many boids, None emits forever
+    pub limit: Option<u32>,
+    /// Number of boids spawned so far
+    pub emitted: u32,
+    /// Fraction of a boid carried over to the next tick
+    accumulated: f32,
+}
+
+impl BoidEmitter {
+    /// Create an emitter spawning `rate` boids per second moving in `direction`,
+    /// spread up to half of `spread` radians either way
+    pub fn new(rate: f32, direction: Vec2, spread: f32) -> Self {
+        Self {
+            rate,
+            radius: 0.0,
+            velocity: SpawnVelocity::new(SpawnHeading::Aligned {
+                angle: direction.to_angle(),
+                spread,
+            }),
+            limit: None,
+            emitted: 0,
+            accumulated: 0.0,
+        }
+    }
+
+    /// Set the radius around the emitter boids are spawned in
+    pub fn with_radius(mut self, radius: f32) -> Self {
+        self.radius = radius;
+        self
+    }
+
+    /// Set the initial heading and speed of the spawned boids
+    pub fn with_velocity(mut self, velocity: SpawnVelocity) -> Self {
+        self.velocity = velocity;
+        self
+    }
+
+    /// Stop after spawning `limit` boids
+    pub fn with_limit(mut self, limit: u32) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+}
+
+/// Despawns boids that enter the radius around its Transform.
+/// Their trails are left to finish on their own
+#[derive(Component, Reflect, Clone)]
+#[reflect(Component)]
+pub struct BoidSink {
+    pub radius: f32,
+}
+
+impl BoidSink {
+    pub fn new(radius: f32) -> Self {
+        Self { radius }
+    }
+}
+
+/// Spawn the boids each emitter is owed for the time since the last tick
+pub fn emit_boids(
+    mut emitters: Query<(&mut BoidEmitter, &Transform)>,
+    mut spawner: BoidSpawner,
+    time: Res<Time>,
+) {
+    for (mut emitter, transform) in emitters.iter_mut() {
+        emitter.accumulated += emitter.rate.max(0.0) * time.delta_seconds();
+        let mut count = emitter.accumulated.floor() as u32;
+        emitter.accumulated -= count as f32;
+        if let Some(limit) = emitter.limit {
+            count = count.min(limit.saturating_sub(emitter.emitted));
+        }
+
+        let centre = transform.translation.truncate();
+        for _ in 0..count {
+            let radius = emitter.radius * spawner.rng().gen::<f32>().sqrt();
+            let angle = spawner.rng().gen::<f32>() * std::f32::consts::TAU;
+            let position = centre + Vec2::from_angle(angle) * radius;
+            spawner.spawn(position, &emitter.velocity, centre);
+        }
+        emitter.emitted += count;
+    }
+}
+
+/// Despawn every boid inside the radius of a sink
+pub fn sink_boids(
+    mut commands: Commands,
+    sinks: Query<(&BoidSink, &Transform)>,
+    boids: Query<(Entity, &Transform), (With<Boid>, Without<Player>)>,
+) {
+    if sinks.is_empty() {
+        return;
+    }
+    for (entity, transform) in boids.iter() {
+        let position = transform.translation.truncate();
+        let sunk = sinks.iter().any(|(sink, sink_transform)| {
+            sink_transform
+                .translation
+                .truncate()
+                .distance_squared(position)
+                < sink.radius * sink.radius
+        });
+        if sunk {
+            commands.entity(entity).despawn();
+        }
+    }
+}
@@ -0,0 +1,172 @@
+use crate::boid::Settings;
+use crate::schedule::SimulationSet;
+use bevy::prelude::*;
+use bevy::window::PrimaryWindow;
+
+#[allow(unused_imports)]
+pub mod prelude {
+    pub use super::{FlowField, FlowFieldPlugin, FlowSource};
+}
+
+pub struct FlowFieldPlugin;
+
+impl Plugin for FlowFieldPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<FlowSource>();
+        app.init_resource::<FlowField>();
+        app.add_systems(FixedUpdate, update_flow_field.in_set(SimulationSet::Sense));
+        app.add_systems(Update, draw_flow_field.in_set(SimulationSet::RenderSync));
+    }
+}
+
+/// An environmental force that pushes boids around. Positional sources use the
+/// entity's Transform as their centre
+#[allow(dead_code)]
+#[derive(Component, Reflect, Clone)]
+#[reflect(Component)]
+pub enum FlowSource {
+    /// Constant flow everywhere in the world
+    Wind(Vec2),
+    /// Flow circling around the entity, positive strength is anticlockwise
+    Vortex { strength: f32, radius: f32 },
+    /// Flow pointing away from the entity, negative strength makes it a sink
+    Radial { strength: f32, radius: f32 },
+    /// Smoothly varying procedural flow that changes over time
+    Noise {
+        strength: f32,
+        /// Size of the noise features in world units
+        scale: f32,
+        /// How fast the noise changes over time
+        speed: f32,
+    },
+}
+
+impl FlowSource {
+    /// Flow velocity of this source at a position in the world
+    pub fn sample(&self, centre: Vec2, position: Vec2, elapsed_seconds: f32) -> Vec2 {
+        match *self {
+            Self::Wind(wind) => wind,
+            Self::Vortex { strength, radius } => {
+                let offset = position - centre;
+                offset.perp().normalize_or_zero() * strength * falloff(offset.length(), radius)
+            }
+            Self::Radial { strength, radius } => {
+                let offset = position - centre;
+                offset.normalize_or_zero() * strength * falloff(offset.length(), radius)
+            }
+            Self::Noise {
+                strength,
+                scale,
+                speed,
+            } => {
+                let point = position / scale.max(0.0001);
+                let time = elapsed_seconds * speed;
+                let angle =
+                    value_noise(point + Vec2::new(time, time * 0.5)) * std::f32::consts::TAU * 2.0;
+                Vec2::from_angle(angle) * strength
+            }
+        }
+    }
+}
+
+/// Smooth falloff from 1 at the centre to 0 at the radius
+fn falloff(distance: f32, radius: f32) -> f32 {
+    if distance >= radius {
+        return 0.0;
+    }
+    let t = 1.0 - distance / radius;
+    t * t * (3.0 - 2.0 * t)
+}
+
+/// Hash a lattice point to a pseudo random value between 0 and 1
+fn hash(x: i32, y: i32) -> f32 {
+    let mut h = (x as u32).wrapping_mul(374761393) ^ (y as u32).wrapping_mul(668265263);
+    h = (h ^ (h >> 13)).wrapping_mul(1274126177);
+    h ^= h >> 16;
+    h as f32 / u32::MAX as f32
+}
+
+/// Smoothly interpolated value noise between 0 and 1
+fn value_noise(point: Vec2) -> f32 {
+    let cell = point.floor();
+    let local = point - cell;
+    let (x, y) = (cell.x as i32, cell.y as i32);
+    // Smoothstep so the field has no visible creases along cell edges
+    let t = local * local * (Vec2::splat(3.0) - 2.0 * local);
+
+    let bottom = hash(x, y) + (hash(x + 1, y) - hash(x, y)) * t.x;
+    let top = hash(x, y + 1) + (hash(x + 1, y + 1) - hash(x, y + 1)) * t.x;
+    bottom + (top - bottom) * t.y
+}
+
+/// The flow sources as they are this tick. `boid_update` samples it at every stage of the
+/// integrator, so the flow follows the boids as they move during the step
+#[derive(Resource, Default)]
+pub struct FlowField {
+    /// Every source with its centre
+    sources: Vec<(FlowSource, Vec2)>,
+    elapsed_seconds: f32,
+}
+
+impl FlowField {
+    /// Sum the flow of every source at a position
+    pub fn sample(&self, position: Vec2) -> Vec2 {
+        self.sources
+            .iter()
+            .map(|(source, centre)| source.sample(*centre, position, self.elapsed_seconds))
+            .sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sources.is_empty()
+    }
+}
+
+/// Copy the flow sources into the FlowField for this tick
+fn update_flow_field(
+    sources: Query<(&FlowSource, Option<&Transform>)>,
+    mut flow_field: ResMut<FlowField>,
+    time: Res<Time>,
+) {
+    flow_field.sources.clear();
+    flow_field
+        .sources
+        .extend(sources.iter().map(|(source, transform)| {
+            let centre = transform.map_or(Vec2::ZERO, |t| t.translation.truncate());
+            (source.clone(), centre)
+        }));
+    flow_field.elapsed_seconds = time.elapsed_seconds();
+}
+
+/// Draw arrows on a grid across the screen showing the flow field of the last tick
+fn draw_flow_field(
+    mut gizmos: Gizmos,
+    flow_field: Res<FlowField>,
+    window_query: Query<&Window, With<PrimaryWindow>>,
+    camera_query: Query<&Transform, With<Camera>>,
+    settings: Res<Settings>,
+) {
+    if !settings.show_flow_field || flow_field.is_empty() {
+        return;
+    }
+    const SPACING: f32 = 40.0;
+    let window = window_query.get_single().expect("No window found");
+    let camera_transform = camera_query.get_single().expect("No camera found");
+    let min = camera_transform.translation.truncate() - window.size() / 2.0;
+    let columns = (window.width() / SPACING) as i32 + 1;
+    let rows = (window.height() / SPACING) as i32 + 1;
+    for column in 0..columns {
+        for row in 0..rows {
+            let position = min + Vec2::new(column as f32, row as f32) * SPACING;
+            let flow = flow_field.sample(position);
+            if flow == Vec2::ZERO {
+                continue;
+            }
+            // Scale arrows so the longest fits inside a grid cell
+            let arrow = flow.clamp_length_max(SPACING * 0.8);
+            gizmos
+                .arrow_2d(position, position + arrow, Color::srgba(0.4, 0.8, 1.0, 0.6))
+                .with_tip_length(5.0);
+        }
+    }
+}
@@ -0,0 +1,601 @@
+use crate::boid::{
+    resolve_max_speed, Acceleration, Boid, BoidParameters, MaxVelocity, Settings, Velocity,
+};
+use crate::emitter::emit_boids;
+use crate::player::components::Player;
+use crate::random::SimulationRng;
+use crate::schedule::{SimulationSet, SteerSet};
+use crate::steering::arrive;
+use bevy::asset::LoadState;
+use bevy::prelude::*;
+use bevy::render::render_resource::TextureFormat;
+use rand::seq::SliceRandom;
+
+#[allow(unused_imports)]
+pub mod prelude {
+    pub use super::{FlockFormation, Formation, FormationPlugin, FormationShape, FormationTarget};
+}
+
+pub struct FormationPlugin;
+
+impl Plugin for FormationPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<FlockFormation>();
+        app.register_type::<FormationTarget>();
+        // Emitting draws from the simulation RNG too, so they run in a fixed order
+        app.add_systems(
+            FixedUpdate,
+            assign_formation
+                .after(emit_boids)
+                .in_set(SimulationSet::Sense),
+        );
+        app.add_systems(FixedUpdate, seek_formation.in_set(SteerSet::Forces));
+    }
+}
+
+/// Flocks larger than this are assigned greedily and then improved, rather than exactly
+const EXACT_ASSIGNMENT_LIMIT: usize = 256;
+/// How many times the greedy assignment is swept for improving swaps
+const SWAP_PASSES: usize = 4;
+/// How many grid cells around a boid are searched for targets to swap with
+const SWAP_RADIUS: usize = 1;
+
+/// Where the target points of a formation come from
+#[derive(Clone)]
+pub enum FormationShape {
+    /// Every pixel of the image with an alpha above one half is a target point,
+    /// one pixel is `scale` world units wide
+    Image { image: Handle<Image>, scale: f32 },
+    /// Target points relative to the centre of the formation
+    Points(Vec<Vec2>),
+}
+
+impl FormationShape {
+    /// Target points relative to the centre, or None while the image is still loading.
+    /// Fails with the format of images that can't be converted to RGBA
+    fn points(&self, images: &Assets<Image>) -> Option<Result<Vec<Vec2>, TextureFormat>> {
+        match self {
+            FormationShape::Points(points) => Some(Ok(points.clone())),
+            FormationShape::Image { image, scale } => {
+                let image = images.get(image)?;
+                let Some(image) = image.convert(TextureFormat::Rgba8UnormSrgb) else {
+                    return Some(Err(image.texture_descriptor.format));
+                };
+                let (width, height) = (image.width() as usize, image.height() as usize);
+                let half_size = Vec2::new(width as f32, height as f32) / 2.0;
+                let mut points = Vec::new();
+                for (index, pixel) in image.data.chunks_exact(4).enumerate() {
+                    if pixel[3] < 128 {
+                        continue;
+                    }
+                    // Image rows go down, world y goes up
+                    let (x, y) = (index % width, index / width);
+                    let pixel_centre = Vec2::new(x as f32 + 0.5, (height - y) as f32 - 0.5);
+                    points.push((pixel_centre - half_size) * *scale);
+                }
+                Some(Ok(points))
+            }
+        }
+    }
+}
+
+/// A shape for the flock to form, blended with the normal flocking forces.
+/// Boids can only come to rest on their targets if `Settings::min_speed` is 0
+#[derive(Clone)]
+pub struct Formation {
+    pub shape: FormationShape,
+    /// World position the shape is centred on
+    pub centre: Vec2,
+    /// Multiplier for the force towards the target points
+    pub weight: f32,
+    /// Distance from the target at which boids start slowing down
+    pub slowing_radius: f32,
+}
+
+#[allow(dead_code)]
+impl Formation {
+    /// Form the opaque pixels of an image, usually loaded from `assets/`
+    pub fn image(image: Handle<Image>, scale: f32) -> Self {
+        Self::new(FormationShape::Image { image, scale })
+    }
+
+    /// Form a set of points relative to the centre
+    pub fn points(points: Vec<Vec2>) -> Self {
+        Self::new(FormationShape::Points(points))
+    }
+
+    fn new(shape: FormationShape) -> Self {
+        Self {
+            shape,
+            centre: Vec2::ZERO,
+            weight: 2.0,
+            slowing_radius: 50.0,
+        }
+    }
+
+    /// Set the world position the shape is centred on
+    pub fn with_centre(mut self, centre: Vec2) -> Self {
+        self.centre = centre;
+        self
+    }
+
+    /// Set the multiplier for the force towards the target points
+    pub fn with_weight(mut self, weight: f32) -> Self {
+        self.weight = weight;
+        self
+    }
+
+    /// Set the distance from the target at which boids start slowing down
+    pub fn with_slowing_radius(mut self, slowing_radius: f32) -> Self {
+        self.slowing_radius = slowing_radius;
+        self
+    }
+}
+
+/// Formation of the whole flock. Setting this gives every boid a `FormationTarget`,
+/// setting another formation morphs the flock into it and None removes the targets.
+/// Boids spawned afterwards, by emitters for example, only get a target when the
+/// formation is set again
+#[derive(Resource, Default)]
+pub struct FlockFormation(pub Option<Formation>);
+
+/// The point in the formation a boid steers towards
+#[derive(Component, Reflect, Clone)]
+#[reflect(Component)]
+pub struct FormationTarget {
+    pub target: Vec2,
+    /// Multiplier for the force towards the target
+    pub weight: f32,
+    /// Distance from the target at which the boid starts slowing down
+    pub slowing_radius: f32,
+}
+
+/// Assign a target point to every boid when the FlockFormation resource changes,
+/// waiting for the image to load first
+fn assign_formation(
+    mut commands: Commands,
+    flock_formation: Res<FlockFormation>,
+    images: Res<Assets<Image>>,
+    asset_server: Res<AssetServer>,
+    boid_query: Query<(Entity, &Transform), (With<Boid>, Without<Player>)>,
+    mut rng: ResMut<SimulationRng>,
+    mut pending: Local<bool>,
+) {
+    if flock_formation.is_changed() {
+        *pending = true;
+    }
+    if !*pending {
+        return;
+    }
+
+    let Some(formation) = &flock_formation.0 else {
+        *pending = false;
+        for (entity, _) in boid_query.iter() {
+            commands.entity(entity).remove::<FormationTarget>();
+        }
+        return;
+    };
+    let points = match formation.shape.points(&images) {
+        Some(Ok(points)) => points,
+        Some(Err(format)) => {
+            *pending = false;
+            warn!("Formation image has format {format:?}, which can't be converted to RGBA");
+            return;
+        }
+        None => {
+            if let FormationShape::Image { image, .. } = &formation.shape {
+                if let Some(LoadState::Failed(error)) = asset_server.get_load_state(image) {
+                    *pending = false;
+                    warn!("Formation image failed to load: {error}");
+                }
+            }
+            return;
+        }
+    };
+    *pending = false;
+    if points.is_empty() {
+        warn!("Formation has no target points");
+        return;
+    }
+
+    let (entities, positions): (Vec<Entity>, Vec<Vec2>) = boid_query
+        .iter()
+        .map(|(entity, transform)| (entity, transform.translation.truncate()))
+        .unzip();
+    let targets: Vec<Vec2> = if points.len() > positions.len() {
+        // Spread the boids over the whole shape
+        points
+            .choose_multiple(&mut rng.0, positions.len())
+            .map(|point| *point + formation.centre)
+            .collect()
+    } else {
+        // Not enough points for every boid, some have to share
+        (0..positions.len())
+            .map(|i| points[i % points.len()] + formation.centre)
+            .collect()
+    };
+
+    for (entity, target) in entities.iter().zip(assign_targets(&positions, &targets)) {
+        commands.entity(*entity).insert(FormationTarget {
+            target: targets[target],
+            weight: formation.weight,
+            slowing_radius: formation.slowing_radius,
+        });
+    }
+}
+
+/// Add a force towards the formation target of every boid that has one
+pub fn seek_formation(
+    mut query: Query<(
+        &FormationTarget,
+        &mut Acceleration,
+        &Transform,
+        &Velocity,
+        Option<&MaxVelocity>,
+        Option<&BoidParameters>,
+    )>,
+    settings: Res<Settings>,
+) {
+    for (target, mut acceleration, transform, velocity, max_velocity, parameters) in
+        query.iter_mut()
+    {
+        let max_speed = resolve_max_speed(max_velocity, parameters, &settings);
+        let force = arrive(
+            transform.translation.truncate(),
+            velocity.0,
+            target.target,
+            target.slowing_radius,
+            max_speed,
+            settings.max_force,
+        );
+        acceleration.0 += force * target.weight;
+    }
+}
+
+/// Index of the target for each position, so the total distance travelled is as small as
+/// possible. Exact for small flocks, larger flocks take the nearest free target one boid at a
+/// time and are improved by swapping targets between nearby boids. Both slices must be the
+/// same length
+pub fn assign_targets(positions: &[Vec2], targets: &[Vec2]) -> Vec<usize> {
+    debug_assert_eq!(positions.len(), targets.len());
+    if positions.len() <= EXACT_ASSIGNMENT_LIMIT {
+        // NaN positions would stall the solver, treat them as very far away
+        let cost = |position: usize, target: usize| {
+            positions[position].distance(targets[target]).min(1.0e9)
+        };
+        hungarian(positions.len(), cost)
+    } else {
+        let grid = TargetGrid::new(targets);
+        let mut assignment = greedy(positions, targets, &grid);
+        improve_by_swaps(&mut assignment, positions, targets, &grid);
+        assignment
+    }
+}
+
+/// Hungarian algorithm with potentials, O(n^3)
+#[allow(clippy::needless_range_loop)]
+fn hungarian(n: usize, cost: impl Fn(usize, usize) -> f32) -> Vec<usize> {
+    // 1-indexed, column 0 is a virtual column used to start each augmenting path
+    let mut u = vec![0.0; n + 1];
+    let mut v = vec![0.0; n + 1];
+    // Row matched to each column, 0 if unmatched
+    let mut matched = vec![0; n + 1];
+    let mut way = vec![0; n + 1];
+    for row in 1..=n {
+        matched[0] = row;
+        let mut column = 0;
+        let mut min_slack = vec![f32::INFINITY; n + 1];
+        let mut used = vec![false; n + 1];
+        loop {
+            used[column] = true;
+            let current_row = matched[column];
+            let mut delta = f32::INFINITY;
+            let mut next_column = 0;
+            for j in 1..=n {
+                if used[j] {
+                    continue;
+                }
+                let slack = cost(current_row - 1, j - 1) - u[current_row] - v[j];
+                if slack < min_slack[j] {
+                    min_slack[j] = slack;
+                    way[j] = column;
+                }
+                if min_slack[j] < delta {
+                    delta = min_slack[j];
+                    next_column = j;
+                }
+            }
+            for j in 0..=n {
+                if used[j] {
+                    u[matched[j]] += delta;
+                    v[j] -= delta;
+                } else {
+                    min_slack[j] -= delta;
+                }
+            }
+            column = next_column;
+            if matched[column] == 0 {
+                break;
+            }
+        }
+        // Flip the augmenting path
+        while column != 0 {
+            let previous = way[column];
+            matched[column] = matched[previous];
+            column = previous;
+        }
+    }
+
+    let mut assignment = vec![0; n];
+    for j in 1..=n {
+        assignment[matched[j] - 1] = j - 1;
+    }
+    assignment
+}
+
+/// Uniform grid over the target points with about one target per cell, so the targets near a
+/// position can be found without looking at all of them
+struct TargetGrid {
+    min: Vec2,
+    cell_size: f32,
+    columns: usize,
+    rows: usize,
+    /// Targets in each cell, row by row
+    cells: Vec<Vec<usize>>,
+}
+
+impl TargetGrid {
+    fn new(targets: &[Vec2]) -> Self {
+        let (min, max) = targets.iter().fold(
+            (Vec2::splat(f32::INFINITY), Vec2::splat(f32::NEG_INFINITY)),
+            |(min, max), target| (min.min(*target), max.max(*target)),
+        );
+        let size = (max - min).max(Vec2::ZERO);
+        let count = targets.len().max(1) as f32;
+        // The second term stops long thin shapes from having far more cells than targets
+        let cell_size = (size.x * size.y / count)
+            .sqrt()
+            .max(size.max_element() / count)
+            .max(f32::EPSILON);
+        let columns = (size.x / cell_size) as usize + 1;
+        let rows = (size.y / cell_size) as usize + 1;
+        let mut grid = Self {
+            min,
+            cell_size,
+            columns,
+            rows,
+            cells: vec![Vec::new(); columns * rows],
+        };
+        for (index, target) in targets.iter().enumerate() {
+            let (column, row) = grid.cell(*target);
+            grid.cells[row * columns + column].push(index);
+        }
+        grid
+    }
+
+    /// Cell containing a position, positions outside the grid are clamped to the border
+    fn cell(&self, position: Vec2) -> (usize, usize) {
+        let cell = ((position - self.min) / self.cell_size).floor();
+        // NaN casts to 0
+        (
+            (cell.x.max(0.0) as usize).min(self.columns - 1),
+            (cell.y.max(0.0) as usize).min(self.rows - 1),
+        )
+    }
+
+    /// Indices of the cells exactly `ring` cells away from a cell in either direction
+    fn ring(&self, (column, row): (usize, usize), ring: usize) -> impl Iterator<Item = usize> + '_ {
+        let (column, row, ring) = (column as i64, row as i64, ring as i64);
+        (row - ring..=row + ring)
+            .flat_map(move |y| (column - ring..=column + ring).map(move |x| (x, y)))
+            .filter(move |(x, y)| (x - column).abs() == ring || (y - row).abs() == ring)
+            .filter(move |(x, y)| {
+                *x >= 0 && *y >= 0 && (*x as usize) < self.columns && (*y as usize) < self.rows
+            })
+            .map(move |(x, y)| y as usize * self.columns + x as usize)
+    }
+}
+
+/// Give each position in turn the nearest target that is still free, searching the grid in
+/// growing rings around it
+fn greedy(positions: &[Vec2], targets: &[Vec2], grid: &TargetGrid) -> Vec<usize> {
+    let mut free = grid.cells.clone();
+    let max_ring = grid.columns.max(grid.rows);
+    positions
+        .iter()
+        .map(|position| {
+            let home = grid.cell(*position);
+            let mut best: Option<(f32, usize, usize)> = None;
+            for ring in 0..=max_ring {
+                // Every target in this ring or further out is at least this far away
+                let nearest_possible = ring.saturating_sub(1) as f32 * grid.cell_size;
+                if best.is_some_and(|(distance, _, _)| distance <= nearest_possible) {
+                    break;
+                }
+                for cell in grid.ring(home, ring) {
+                    for (slot, target) in free[cell].iter().enumerate() {
+                        // NaN positions take any free target
+                        let distance = position.distance(targets[*target]).min(f32::MAX);
+                        if best.is_none_or(|(best_distance, _, _)| distance < best_distance) {
+                            best = Some((distance, cell, slot));
+                        }
+                    }
+                }
+            }
+            let (_, cell, slot) = best.expect("There is a free target for every position");
+            free[cell].swap_remove(slot)
+        })
+        .collect()
+}
+
+/// Swap the targets of two boids whenever that shortens their combined travel, only trying
+/// the boids whose targets are near the first boid
+fn improve_by_swaps(
+    assignment: &mut [usize],
+    positions: &[Vec2],
+    targets: &[Vec2],
+    grid: &TargetGrid,
+) {
+    let cost =
+        |position: usize, target: usize| positions[position].distance(targets[target]).min(1.0e9);
+    let mut owner = vec![0; targets.len()];
+    for (position, target) in assignment.iter().enumerate() {
+        owner[*target] = position;
+    }
+    for _ in 0..SWAP_PASSES {
+        let mut improved = false;
+        for a in 0..assignment.len() {
+            let home = grid.cell(positions[a]);
+            for ring in 0..=SWAP_RADIUS {
+                for cell in grid.ring(home, ring) {
+                    for target_b in grid.cells[cell].iter().copied() {
+                        let (b, target_a) = (owner[target_b], assignment[a]);
+                        if b != a
+                            && cost(a, target_b) + cost(b, target_a)
+                                < cost(a, target_a) + cost(b, target_b)
+                        {
+                            assignment.swap(a, b);
+                            owner.swap(target_a, target_b);
+                            improved = true;
+                        }
+                    }
+                }
+            }
+        }
+        if !improved {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    fn random_points(rng: &mut StdRng, count: usize) -> Vec<Vec2> {
+        (0..count)
+            .map(|_| Vec2::new(rng.gen_range(0.0..100.0), rng.gen_range(0.0..100.0)))
+            .collect()
+    }
+
+    fn total_distance(positions: &[Vec2], targets: &[Vec2], assignment: &[usize]) -> f32 {
+        positions
+            .iter()
+            .zip(assignment)
+            .map(|(position, target)| position.distance(targets[*target]))
+            .sum()
+    }
+
+    fn is_permutation(assignment: &[usize]) -> bool {
+        let mut sorted = assignment.to_vec();
+        sorted.sort_unstable();
+        sorted.iter().copied().eq(0..assignment.len())
+    }
+
+    /// Smallest total distance over every possible assignment
+    fn brute_force(positions: &[Vec2], targets: &[Vec2]) -> f32 {
+        fn search(positions: &[Vec2], targets: &[Vec2], used: &mut [bool], total: f32) -> f32 {
+            let Some((position, rest)) = positions.split_first() else {
+                return total;
+            };
+            let mut best = f32::INFINITY;
+            for target in 0..targets.len() {
+                if used[target] {
+                    continue;
+                }
+                used[target] = true;
+                let distance = position.distance(targets[target]);
+                best = best.min(search(rest, targets, used, total + distance));
+                used[target] = false;
+            }
+            best
+        }
+        search(positions, targets, &mut vec![false; targets.len()], 0.0)
+    }
+
+    #[test]
+    fn hungarian_matches_brute_force() {
+        let mut rng = StdRng::seed_from_u64(1);
+        for count in 1..=7 {
+            for _ in 0..10 {
+                let positions = random_points(&mut rng, count);
+                let targets = random_points(&mut rng, count);
+                let assignment = hungarian(count, |position, target| {
+                    positions[position].distance(targets[target])
+                });
+                assert!(is_permutation(&assignment));
+                let optimal = brute_force(&positions, &targets);
+                let total = total_distance(&positions, &targets, &assignment);
+                assert!((total - optimal).abs() < 1.0e-3, "{total} != {optimal}");
+            }
+        }
+    }
+
+    #[test]
+    fn small_flocks_are_assigned_exactly() {
+        let mut rng = StdRng::seed_from_u64(2);
+        let positions = random_points(&mut rng, 6);
+        let targets = random_points(&mut rng, 6);
+        let assignment = assign_targets(&positions, &targets);
+        let total = total_distance(&positions, &targets, &assignment);
+        assert!((total - brute_force(&positions, &targets)).abs() < 1.0e-3);
+    }
+
+    #[test]
+    fn nan_positions_still_get_a_target() {
+        let positions = vec![Vec2::NAN, Vec2::ZERO, Vec2::X];
+        let targets = vec![Vec2::X, Vec2::Y, Vec2::ZERO];
+        assert!(is_permutation(&assign_targets(&positions, &targets)));
+
+        let mut positions = random_points(&mut StdRng::seed_from_u64(3), 1000);
+        positions[10] = Vec2::NAN;
+        let targets = random_points(&mut StdRng::seed_from_u64(4), 1000);
+        assert!(is_permutation(&assign_targets(&positions, &targets)));
+    }
+
+    #[test]
+    fn large_flocks_find_targets_on_their_own_positions() {
+        let mut rng = StdRng::seed_from_u64(5);
+        let positions = random_points(&mut rng, 1000);
+        let mut targets = positions.clone();
+        targets.reverse();
+        let assignment = assign_targets(&positions, &targets);
+        assert!(is_permutation(&assignment));
+        assert_eq!(total_distance(&positions, &targets, &assignment), 0.0);
+    }
+
+    #[test]
+    fn large_flocks_are_assigned_close_to_optimally() {
+        // Every boid moves the same way, so the optimal total is the offset length per boid
+        let positions: Vec<Vec2> = (0..40 * 40)
+            .map(|i| Vec2::new((i % 40) as f32, (i / 40) as f32) * 10.0)
+            .collect();
+        let offset = Vec2::new(3.0, 4.0);
+        let targets: Vec<Vec2> = positions.iter().rev().map(|p| *p + offset).collect();
+        let assignment = assign_targets(&positions, &targets);
+        assert!(is_permutation(&assignment));
+        let total = total_distance(&positions, &targets, &assignment);
+        let optimal = positions.len() as f32 * offset.length();
+        assert!(total < optimal * 1.01, "{total} vs {optimal}");
+    }
+
+    #[test]
+    fn swaps_improve_random_large_assignments() {
+        let mut rng = StdRng::seed_from_u64(6);
+        let positions = random_points(&mut rng, 2000);
+        let targets = random_points(&mut rng, 2000);
+        let grid = TargetGrid::new(&targets);
+        let greedy_assignment = greedy(&positions, &targets, &grid);
+        let mut improved = greedy_assignment.clone();
+        improve_by_swaps(&mut improved, &positions, &targets, &grid);
+        assert!(is_permutation(&improved));
+        let greedy_total = total_distance(&positions, &targets, &greedy_assignment);
+        let improved_total = total_distance(&positions, &targets, &improved);
+        assert!(improved_total <= greedy_total);
+        // Far better than keeping the order the boids came in
+        let identity: Vec<usize> = (0..positions.len()).collect();
+        assert!(improved_total < total_distance(&positions, &targets, &identity) / 4.0);
+    }
+}
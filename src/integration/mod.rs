@@ -0,0 +1,187 @@
+use bevy::prelude::*;
+
+/// Numerical scheme used to advance position and velocity each step
+#[derive(Reflect, Clone, Copy, Default, PartialEq, Debug)]
+pub enum Integrator {
+    /// Move with the old velocity, then apply acceleration
+    #[default]
+    ExplicitEuler,
+    /// Apply acceleration first, then move with the new velocity
+    SemiImplicitEuler,
+    /// Second order, averages the acceleration at the start and end of the step
+    VelocityVerlet,
+    /// Fourth order Runge-Kutta
+    Rk4,
+}
+
+impl Integrator {
+    /// Advance a position and velocity by `dt` seconds.
+    /// `acceleration` is evaluated for a position and velocity, higher order schemes call it
+    /// several times per step. The schemes only differ when it depends on them, for a
+    /// constant acceleration Verlet and RK4 give the same, exact result. `boid_update` passes
+    /// the held Acceleration plus the `StateForces` of the boid
+    pub fn step(
+        &self,
+        position: Vec2,
+        velocity: Vec2,
+        dt: f32,
+        acceleration: impl Fn(Vec2, Vec2) -> Vec2,
+    ) -> (Vec2, Vec2) {
+        match self {
+            Self::ExplicitEuler => {
+                let a = acceleration(position, velocity);
+                (position + velocity * dt, velocity + a * dt)
+            }
+            Self::SemiImplicitEuler => {
+                let velocity = velocity + acceleration(position, velocity) * dt;
+                (position + velocity * dt, velocity)
+            }
+            Self::VelocityVerlet => {
+                let a0 = acceleration(position, velocity);
+                let position = position + velocity * dt + 0.5 * a0 * dt * dt;
+                let a1 = acceleration(position, velocity + a0 * dt);
+                (position, velocity + 0.5 * (a0 + a1) * dt)
+            }
+            Self::Rk4 => {
+                let k1_x = velocity;
+                let k1_v = acceleration(position, velocity);
+
+                let k2_x = velocity + k1_v * dt / 2.0;
+                let k2_v = acceleration(position + k1_x * dt / 2.0, k2_x);
+
+                let k3_x = velocity + k2_v * dt / 2.0;
+                let k3_v = acceleration(position + k2_x * dt / 2.0, k3_x);
+
+                let k4_x = velocity + k3_v * dt;
+                let k4_v = acceleration(position + k3_x * dt, k4_x);
+
+                (
+                    position + (k1_x + 2.0 * k2_x + 2.0 * k3_x + k4_x) * dt / 6.0,
+                    velocity + (k1_v + 2.0 * k2_v + 2.0 * k3_v + k4_v) * dt / 6.0,
+                )
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCHEMES: [Integrator; 4] = [
+        Integrator::ExplicitEuler,
+        Integrator::SemiImplicitEuler,
+        Integrator::VelocityVerlet,
+        Integrator::Rk4,
+    ];
+
+    /// Unit spring pulling towards the origin
+    fn spring(position: Vec2, _: Vec2) -> Vec2 {
+        -position
+    }
+
+    /// Linear drag, velocity decays as e^-t
+    fn drag(_: Vec2, velocity: Vec2) -> Vec2 {
+        -velocity
+    }
+
+    /// Step `steps` times from the initial position and velocity
+    fn simulate(
+        integrator: Integrator,
+        (mut position, mut velocity): (Vec2, Vec2),
+        steps: u32,
+        dt: f32,
+        acceleration: fn(Vec2, Vec2) -> Vec2,
+    ) -> (Vec2, Vec2) {
+        for _ in 0..steps {
+            (position, velocity) = integrator.step(position, velocity, dt, acceleration);
+        }
+        (position, velocity)
+    }
+
+    #[test]
+    fn schemes_differ_on_a_spring() {
+        let positions = SCHEMES.map(|integrator| {
+            simulate(integrator, (Vec2::X, Vec2::ZERO), 1, 0.1, spring)
+                .0
+                .x
+        });
+        for i in 0..positions.len() {
+            for j in i + 1..positions.len() {
+                assert_ne!(
+                    positions[i], positions[j],
+                    "{:?} and {:?}",
+                    SCHEMES[i], SCHEMES[j]
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn higher_order_schemes_are_more_accurate_on_a_spring() {
+        // x = cos(t) for a unit spring released from rest at x = 1
+        let (steps, dt) = (100, 0.05);
+        let exact = (steps as f32 * dt).cos();
+        let error = |integrator| {
+            (simulate(integrator, (Vec2::X, Vec2::ZERO), steps, dt, spring)
+                .0
+                .x
+                - exact)
+                .abs()
+        };
+        assert!(error(Integrator::Rk4) < error(Integrator::VelocityVerlet));
+        assert!(error(Integrator::VelocityVerlet) < error(Integrator::ExplicitEuler));
+        assert!(error(Integrator::Rk4) < 1.0e-4);
+    }
+
+    #[test]
+    fn explicit_euler_gains_energy_on_a_spring() {
+        let energy = |(position, velocity): (Vec2, Vec2)| {
+            0.5 * (position.length_squared() + velocity.length_squared())
+        };
+        let (steps, dt) = (1000, 0.05);
+        assert!(
+            energy(simulate(
+                Integrator::ExplicitEuler,
+                (Vec2::X, Vec2::ZERO),
+                steps,
+                dt,
+                spring
+            )) > 1.0
+        );
+        let verlet = energy(simulate(
+            Integrator::VelocityVerlet,
+            (Vec2::X, Vec2::ZERO),
+            steps,
+            dt,
+            spring,
+        ));
+        assert!((verlet - 0.5).abs() < 0.01);
+    }
+
+    #[test]
+    fn higher_order_schemes_are_more_accurate_with_drag() {
+        // v = e^-t for unit drag starting at v = 1
+        let (steps, dt) = (20, 0.1);
+        let exact = (-(steps as f32) * dt).exp();
+        let error = |integrator| {
+            (simulate(integrator, (Vec2::ZERO, Vec2::X), steps, dt, drag)
+                .1
+                .x
+                - exact)
+                .abs()
+        };
+        assert!(error(Integrator::Rk4) < error(Integrator::VelocityVerlet));
+        assert!(error(Integrator::VelocityVerlet) < error(Integrator::ExplicitEuler));
+    }
+
+    #[test]
+    fn constant_acceleration_is_exact_for_verlet_and_rk4() {
+        let gravity = |_: Vec2, _: Vec2| Vec2::new(0.0, -10.0);
+        for integrator in [Integrator::VelocityVerlet, Integrator::Rk4] {
+            let (position, velocity) = integrator.step(Vec2::ZERO, Vec2::X, 0.5, gravity);
+            assert!(position.abs_diff_eq(Vec2::new(0.5, -1.25), 1.0e-6));
+            assert!(velocity.abs_diff_eq(Vec2::new(1.0, -5.0), 1.0e-6));
+        }
+    }
+}
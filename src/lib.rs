@@ -0,0 +1,56 @@
+//! Boid flocking simulation for Bevy.
+//!
+//! Add `BoidPlugin` to an app with a camera to spawn and simulate a flock. Everything
+//! commonly needed is re-exported from `prelude`.
+
+pub mod appearance;
+pub mod boid;
+pub mod camera;
+pub mod constants;
+pub mod debug;
+pub mod diagnostics;
+pub mod emitter;
+pub mod flow_field;
+pub mod formation;
+pub mod integration;
+pub mod models;
+pub mod path;
+pub mod player;
+pub mod random;
+pub mod schedule;
+pub mod selection;
+pub mod snapshot;
+mod spatial_hash_map;
+pub mod spawn;
+pub mod steering;
+pub mod time_control;
+pub mod trail;
+mod window_resize;
+
+pub mod prelude {
+    pub use crate::appearance::prelude::*;
+    pub use crate::boid::{
+        Acceleration, Boid, BoidParameters, BoidPlugin, BoidSpawner, FlockForces, HeadingNoise,
+        Mass, MaxTurnRate, MaxVelocity, Settings, SimulationBounds, SpawnDistribution, StateForces,
+        Velocity,
+    };
+    pub use crate::camera::CameraPlugin;
+    pub use crate::debug::prelude::*;
+    pub use crate::diagnostics::{NumericalDiagnosticsPlugin, NumericalHealth};
+    pub use crate::emitter::prelude::*;
+    pub use crate::flow_field::prelude::*;
+    pub use crate::formation::prelude::*;
+    pub use crate::integration::Integrator;
+    pub use crate::models::{CollectiveModelsPlugin, FlockingModel};
+    pub use crate::path::prelude::*;
+    pub use crate::player::components::Player;
+    pub use crate::player::PlayerPlugin;
+    pub use crate::random::SimulationRng;
+    pub use crate::schedule::{SimulationSet, SteerSet};
+    pub use crate::selection::prelude::*;
+    pub use crate::snapshot::prelude::*;
+    pub use crate::spawn::prelude::*;
+    pub use crate::steering::prelude::*;
+    pub use crate::time_control::TimeControlPlugin;
+    pub use crate::trail::prelude::*;
+}
@@ -0,0 +1,168 @@
+use crate::boid::{
+    border_adjustment, limit_vec, Acceleration, Boid, Settings, SimulationBounds, Velocity,
+};
+use crate::player::components::Player;
+use crate::random::SimulationRng;
+use crate::schedule::SteerSet;
+use crate::steering::{steer, Steering};
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use bevy_spatial::{kdtree::KDTree2, SpatialAccess};
+use rand::Rng;
+
+pub struct CollectiveModelsPlugin;
+
+impl Plugin for CollectiveModelsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            FixedUpdate,
+            // Steering draws from the simulation RNG too, so they run in a fixed order
+            vicsek_flock
+                .before(steer)
+                .in_set(SteerSet::Rules)
+                .run_if(model_is(FlockingModel::Vicsek)),
+        );
+        app.add_systems(
+            FixedUpdate,
+            couzin_flock
+                .in_set(SteerSet::Rules)
+                .run_if(model_is(FlockingModel::Couzin)),
+        );
+    }
+}
+
+/// The rule set used to move the flock, can be switched at runtime
+#[derive(Reflect, Clone, Copy, Default, PartialEq, Debug)]
+pub enum FlockingModel {
+    /// Reynolds steering with alignment, cohesion and separation forces
+    #[default]
+    Reynolds,
+    /// Constant speed, each boid takes the average heading of its neighbours plus angular noise
+    Vicsek,
+    /// Couzin zones: repulsion inside the separation radius, otherwise orientation to nearby
+    /// boids and attraction to boids further away inside the visual radius
+    Couzin,
+}
+
+/// Run condition that is true while the given model is selected in `Settings`
+pub fn model_is(model: FlockingModel) -> impl FnMut(Res<Settings>) -> bool + Clone {
+    move |settings: Res<Settings>| settings.model == model
+}
+
+/// Vicsek model: every boid moves at the cruise speed in the average direction of the
+/// neighbours inside its visual radius (including itself), perturbed by uniform angular noise.
+/// Headings are replaced once per simulation tick, so the model runs at the rate set by
+/// `Settings::tick_seconds` whatever the frame rate or time scale
+pub fn vicsek_flock(
+    treeaccess: Res<KDTree2<Boid>>,
+    bounds: Res<SimulationBounds>,
+    mut boid_queries: ParamSet<(
+        Query<(Entity, &Velocity), With<Boid>>,
+        Query<
+            (Entity, &mut Acceleration, &mut Velocity, &Transform),
+            (With<Boid>, Without<Player>, Without<Steering>),
+        >,
+    )>,
+    settings: Res<Settings>,
+    mut rng: ResMut<SimulationRng>,
+) {
+    // Headings are read from a snapshot so every boid updates from the same state
+    let headings: HashMap<Entity, Vec2> = boid_queries
+        .p0()
+        .iter()
+        .map(|(entity, velocity)| (entity, velocity.0.normalize_or_zero()))
+        .collect();
+
+    for (entity, mut acceleration, mut velocity, transform) in boid_queries.p1().iter_mut() {
+        let position = transform.translation.truncate();
+        let mut heading_sum = headings.get(&entity).copied().unwrap_or_default();
+        for (_, neighbour) in treeaccess.within_distance(position, settings.visual_radius) {
+            let Some(neighbour) = neighbour else {
+                continue;
+            };
+            if neighbour == entity {
+                continue;
+            }
+            heading_sum += headings.get(&neighbour).copied().unwrap_or_default();
+        }
+
+        let noise = (rng.0.gen::<f32>() - 0.5) * settings.vicsek_noise;
+        let facing = (transform.rotation * Vec3::Y).truncate();
+        let mut heading =
+            Vec2::from_angle(noise).rotate(heading_sum.try_normalize().unwrap_or(facing));
+
+        // Turn back towards the screen when outside of it, headless apps have no border
+        let border = bounds.0.map_or(Vec2::ZERO, |bounds| {
+            border_adjustment(position, bounds, &settings)
+        });
+        if let Some(border) = border.try_normalize() {
+            heading = (heading + border).try_normalize().unwrap_or(border);
+        }
+
+        velocity.0 = heading * settings.cruise_speed;
+        acceleration.0 = Vec2::ZERO;
+    }
+}
+
+/// Couzin zonal model: boids steer away from neighbours in the zone of repulsion, and only if
+/// there are none, align with neighbours in the zone of orientation and move towards
+/// neighbours in the zone of attraction. The border force is added by `boid_update`
+pub fn couzin_flock(
+    treeaccess: Res<KDTree2<Boid>>,
+    mut boid_query: Query<
+        (&mut Acceleration, &Transform, &Velocity),
+        (With<Boid>, Without<Player>, Without<Steering>),
+    >,
+    neighbour_query: Query<(&Transform, &Velocity), With<Boid>>,
+    settings: Res<Settings>,
+) {
+    let repulsion_radius = settings.separation_radius;
+    let orientation_radius = settings.couzin_orientation_radius;
+    let attraction_radius = settings.visual_radius;
+
+    for (mut acceleration, transform, velocity) in boid_query.iter_mut() {
+        let position = transform.translation.truncate();
+        let mut repulsion = Vec2::ZERO;
+        let mut orientation = Vec2::ZERO;
+        let mut attraction = Vec2::ZERO;
+        let mut repelled = false;
+
+        for (_, neighbour) in treeaccess.within_distance(position, attraction_radius) {
+            let Some(neighbour) = neighbour else {
+                continue;
+            };
+            let Ok((other_transform, other_velocity)) = neighbour_query.get(neighbour) else {
+                continue;
+            };
+            let offset = other_transform.translation.truncate() - position;
+            let distance = offset.length();
+            if distance == 0.0 {
+                continue;
+            }
+            let direction = offset / distance;
+            if distance < repulsion_radius {
+                repulsion -= direction;
+                repelled = true;
+            } else if distance < orientation_radius {
+                orientation += other_velocity.0.normalize_or_zero();
+            } else if distance < attraction_radius {
+                attraction += direction;
+            }
+        }
+
+        // Repulsion has absolute priority, otherwise orientation and attraction are averaged
+        let desired = if repelled {
+            repulsion.normalize_or_zero()
+        } else {
+            (orientation.normalize_or_zero() + attraction.normalize_or_zero()).normalize_or_zero()
+        };
+        // With no neighbours keep going in the current direction
+        let desired = desired
+            .try_normalize()
+            .unwrap_or(velocity.0.normalize_or_zero());
+
+        let mut steering = desired * settings.cruise_speed - velocity.0;
+        limit_vec(&mut steering, settings.max_force);
+        acceleration.0 = steering;
+    }
+}
@@ -0,0 +1,195 @@
+use crate::boid::{
+    resolve_max_speed, Acceleration, Boid, BoidParameters, MaxVelocity, Settings, Velocity,
+};
+use crate::player::components::Player;
+use crate::schedule::{SimulationSet, SteerSet};
+use crate::steering::{arrive, seek};
+use bevy::ecs::entity::{EntityMapper, MapEntities};
+use bevy::ecs::reflect::ReflectMapEntities;
+use bevy::prelude::*;
+
+#[allow(unused_imports)]
+pub mod prelude {
+    pub use super::{FlockPath, FollowPath, Path, PathPlugin};
+}
+
+pub struct PathPlugin;
+
+impl Plugin for PathPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<FlockPath>();
+        app.register_type::<Path>().register_type::<FollowPath>();
+        app.add_systems(FixedUpdate, assign_flock_path.in_set(SimulationSet::Sense));
+        app.add_systems(FixedUpdate, follow_paths.in_set(SteerSet::Forces));
+    }
+}
+
+/// A route made of waypoints that boids can follow
+#[derive(Component, Reflect, Clone)]
+#[reflect(Component)]
+pub struct Path {
+    pub waypoints: Vec<Vec2>,
+    /// Whether the last waypoint connects back to the first
+    pub closed: bool,
+    /// How close a boid has to get to a waypoint before moving on to the next one
+    pub radius: f32,
+}
+
+#[allow(dead_code)]
+impl Path {
+    /// Create an open path, followers stop at the last waypoint
+    pub fn polyline(waypoints: Vec<Vec2>) -> Self {
+        Self {
+            waypoints,
+            closed: false,
+            radius: 40.0,
+        }
+    }
+
+    /// Create a closed path, followers loop around forever
+    pub fn closed_loop(waypoints: Vec<Vec2>) -> Self {
+        Self {
+            waypoints,
+            closed: true,
+            radius: 40.0,
+        }
+    }
+
+    /// Set the waypoint arrival radius
+    pub fn with_radius(mut self, radius: f32) -> Self {
+        self.radius = radius;
+        self
+    }
+
+    /// Index of the waypoint after `index`, or None at the end of an open path
+    fn next(&self, index: usize) -> Option<usize> {
+        if index + 1 < self.waypoints.len() {
+            Some(index + 1)
+        } else if self.closed && !self.waypoints.is_empty() {
+            Some(0)
+        } else {
+            None
+        }
+    }
+}
+
+/// Makes an entity steer along a path while still flocking
+#[derive(Component, Reflect, Clone)]
+#[reflect(Component, MapEntities)]
+pub struct FollowPath {
+    /// The entity with the `Path` component to follow
+    pub path: Entity,
+    /// Multiplier for the path following force
+    pub weight: f32,
+    /// Index of the waypoint currently being steered towards
+    pub waypoint: usize,
+}
+
+#[allow(dead_code)]
+impl FollowPath {
+    pub fn new(path: Entity, weight: f32) -> Self {
+        Self {
+            path,
+            weight,
+            waypoint: 0,
+        }
+    }
+}
+
+impl MapEntities for FollowPath {
+    fn map_entities<M: EntityMapper>(&mut self, entity_mapper: &mut M) {
+        self.path = entity_mapper.map_entity(self.path);
+    }
+}
+
+/// Path followed by the whole flock. Setting this adds `FollowPath` to every boid,
+/// setting it back to None removes it again. Boids spawned later join the path too
+#[derive(Resource, Default)]
+pub struct FlockPath(pub Option<FollowPath>);
+
+/// Add or remove FollowPath on every boid when the FlockPath resource changes, and give new
+/// boids, such as those from emitters or a loaded snapshot, the current path
+fn assign_flock_path(
+    mut commands: Commands,
+    flock_path: Res<FlockPath>,
+    boid_query: Query<Entity, (With<Boid>, Without<Player>)>,
+    new_boid_query: Query<Entity, (Added<Boid>, Without<Player>, Without<FollowPath>)>,
+) {
+    if flock_path.is_changed() {
+        for entity in boid_query.iter() {
+            match &flock_path.0 {
+                Some(follow_path) => commands.entity(entity).insert(follow_path.clone()),
+                None => commands.entity(entity).remove::<FollowPath>(),
+            };
+        }
+        return;
+    }
+    // Boids that already follow a path, such as loaded ones, keep their progress
+    if let Some(follow_path) = &flock_path.0 {
+        for entity in new_boid_query.iter() {
+            commands.entity(entity).insert(follow_path.clone());
+        }
+    }
+}
+
+/// Add a force towards the current waypoint of every path follower
+pub fn follow_paths(
+    mut commands: Commands,
+    path_query: Query<&Path>,
+    mut follower_query: Query<(
+        Entity,
+        &mut FollowPath,
+        &mut Acceleration,
+        &Transform,
+        &Velocity,
+        Option<&MaxVelocity>,
+        Option<&BoidParameters>,
+    )>,
+    settings: Res<Settings>,
+) {
+    for (
+        entity,
+        mut follow_path,
+        mut acceleration,
+        transform,
+        velocity,
+        max_velocity,
+        parameters,
+    ) in follower_query.iter_mut()
+    {
+        let Ok(path) = path_query.get(follow_path.path) else {
+            // Path was despawned, stop following it
+            commands.entity(entity).remove::<FollowPath>();
+            continue;
+        };
+        if path.waypoints.is_empty() {
+            continue;
+        }
+
+        let position = transform.translation.truncate();
+        let max_speed = resolve_max_speed(max_velocity, parameters, &settings);
+
+        let mut waypoint = follow_path.waypoint.min(path.waypoints.len() - 1);
+        if position.distance(path.waypoints[waypoint]) < path.radius {
+            if let Some(next) = path.next(waypoint) {
+                waypoint = next;
+            }
+        }
+        follow_path.waypoint = waypoint;
+
+        let target = path.waypoints[waypoint];
+        let force = match path.next(waypoint) {
+            Some(_) => seek(position, velocity.0, target, max_speed, settings.max_force),
+            // Slow down at the end of an open path
+            None => arrive(
+                position,
+                velocity.0,
+                target,
+                path.radius * 2.0,
+                max_speed,
+                settings.max_force,
+            ),
+        };
+        acceleration.0 += force * follow_path.weight;
+    }
+}
@@ -1,13 +1,15 @@
 pub mod components;
 pub mod systems;
 
+use crate::schedule::SteerSet;
 use bevy::prelude::*;
 
 pub struct PlayerPlugin;
 
 impl Plugin for PlayerPlugin {
     fn build(&self, app: &mut App) {
+        app.register_type::<components::Player>();
         app.add_systems(Startup, systems::spawn_player);
-        app.add_systems(Update, systems::move_player);
+        app.add_systems(FixedUpdate, systems::move_player.in_set(SteerSet::Rules));
     }
 }
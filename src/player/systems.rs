@@ -1,5 +1,5 @@
-use crate::boid::{Acceleration, Boid, MaxVelocity, Velocity};
-use crate::constants::{PLAYER_FORCE, PLAYER_MAX_SPEED};
+use crate::boid::{Acceleration, Boid, MaxTurnRate, MaxVelocity, Velocity};
+use crate::constants::{PLAYER_FORCE, PLAYER_MAX_SPEED, PLAYER_MAX_TURN_SPEED};
 use crate::player::components::Player;
 use crate::trail::prelude::*;
 use bevy::prelude::*;
@@ -34,6 +34,7 @@ pub fn spawn_player(
             acceleration,
             velocity,
             MaxVelocity(PLAYER_MAX_SPEED),
+            MaxTurnRate(PLAYER_MAX_TURN_SPEED),
             Boid { weight: 10000.0 },
         ))
         .id();
@@ -0,0 +1,32 @@
+use crate::boid::Settings;
+use bevy::prelude::*;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+/// Seeded random number generator shared by the simulation, so runs with the same
+/// `Settings::seed` can be repeated. Systems that draw from it in the same set must be
+/// ordered against each other, or the order of the draws changes from run to run
+#[derive(Resource)]
+pub struct SimulationRng(pub StdRng);
+
+impl SimulationRng {
+    pub fn new(seed: u64) -> Self {
+        Self(StdRng::seed_from_u64(seed))
+    }
+}
+
+impl FromWorld for SimulationRng {
+    fn from_world(world: &mut World) -> Self {
+        let seed = world
+            .get_resource::<Settings>()
+            .map_or_else(|| Settings::default().seed, |settings| settings.seed);
+        Self::new(seed)
+    }
+}
+
+/// Draw a sample from the standard normal distribution using the Box-Muller transform
+pub fn standard_normal(rng: &mut impl Rng) -> f32 {
+    let u1 = rng.gen::<f32>().max(f32::EPSILON);
+    let u2 = rng.gen::<f32>();
+    (-2.0 * u1.ln()).sqrt() * (std::f32::consts::TAU * u2).cos()
+}
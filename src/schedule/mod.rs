@@ -0,0 +1,54 @@
+use bevy::prelude::*;
+
+/// Stages of a simulation tick, run in the order they are declared. Every stage but
+/// `RenderSync` runs in `FixedUpdate`, once per tick of `Settings::tick_seconds`, so a long
+/// frame runs several complete ticks. `RenderSync` runs in `Update` once per frame.
+/// Add custom systems to these sets to run them at a defined point in the pipeline
+#[derive(SystemSet, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SimulationSet {
+    /// Rebuild neighbour indices from the current positions, the boid KD-tree is updated here
+    IndexRebuild,
+    /// Gather information about the world before steering, such as path assignments
+    Sense,
+    /// Compute the Acceleration of every entity, see `SteerSet`
+    Steer,
+    /// Advance Velocity and Transform from Acceleration and the `StateForces` of the boids,
+    /// then check the results
+    Integrate,
+    /// Update visuals that follow the simulated entities, such as trails and debug drawing.
+    /// Runs in `Update`
+    RenderSync,
+}
+
+/// Sub-stages of `SimulationSet::Steer`
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub enum SteerSet {
+    /// Rules that overwrite Acceleration: flocking models, steering behaviours, player input
+    Rules,
+    /// Forces added on top of the rules: paths and formations. Custom forces should be added
+    /// to Acceleration here. Forces that depend on the position or velocity of a boid, such as
+    /// the flow field, are `StateForces` worked out during integration instead
+    Forces,
+    /// Random perturbations that rotate Velocity directly, after every force has read it
+    Noise,
+}
+
+/// Order the simulation sets, called by `BoidPlugin`
+pub(crate) fn configure_sets(app: &mut App) {
+    app.configure_sets(
+        FixedUpdate,
+        (
+            SimulationSet::IndexRebuild,
+            SimulationSet::Sense,
+            SimulationSet::Steer,
+            SimulationSet::Integrate,
+        )
+            .chain(),
+    );
+    app.configure_sets(
+        FixedUpdate,
+        (SteerSet::Rules, SteerSet::Forces, SteerSet::Noise)
+            .chain()
+            .in_set(SimulationSet::Steer),
+    );
+}
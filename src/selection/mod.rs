@@ -0,0 +1,262 @@
+use crate::boid::{
+    Acceleration, Boid, BoidParameters, FlockForces, Settings, SimulationBounds, Velocity,
+};
+use crate::debug::Selected;
+use crate::models::FlockingModel;
+use crate::player::components::Player;
+use crate::schedule::SimulationSet;
+use bevy::prelude::*;
+use bevy::window::PrimaryWindow;
+use bevy_inspector_egui::bevy_egui::{egui, EguiContexts, EguiPlugin};
+use bevy_spatial::{kdtree::KDTree2, SpatialAccess};
+
+#[allow(unused_imports)]
+pub mod prelude {
+    pub use super::{BoidSelection, SelectionPlugin};
+}
+
+/// Click a boid to select it and inspect it in an egui panel
+pub struct SelectionPlugin;
+
+impl Plugin for SelectionPlugin {
+    fn build(&self, app: &mut App) {
+        if !app.is_plugin_added::<EguiPlugin>() {
+            app.add_plugins(EguiPlugin);
+        }
+        app.init_resource::<BoidSelection>();
+        app.add_systems(
+            Update,
+            (select_boid, sync_selected)
+                .chain()
+                .before(SimulationSet::RenderSync),
+        );
+        app.add_systems(
+            Update,
+            (follow_selection, highlight_neighbours, selection_panel)
+                .chain()
+                .in_set(SimulationSet::RenderSync),
+        );
+    }
+}
+
+/// The boid selected by clicking on it, which is also marked as `Selected`
+#[derive(Resource, Default)]
+pub struct BoidSelection {
+    pub entity: Option<Entity>,
+    /// Keep the camera centred on the selected boid. The screen borders move with the
+    /// camera, so the flock is pulled along too
+    pub follow_camera: bool,
+}
+
+/// How far from a boid a click still selects it, in world units
+const PICK_RADIUS: f32 = 12.0;
+const SELECTION_COLOUR: Color = Color::srgb(1.0, 1.0, 1.0);
+const NEIGHBOUR_COLOUR: Color = Color::srgba(0.3, 0.9, 1.0, 0.6);
+
+/// Select the boid closest to the cursor when the left mouse button is clicked,
+/// clicking empty space clears the selection
+fn select_boid(
+    mouse_input: Res<ButtonInput<MouseButton>>,
+    window_query: Query<&Window, With<PrimaryWindow>>,
+    camera_query: Query<(&Camera, &GlobalTransform)>,
+    boid_query: Query<(Entity, &Transform), (With<Boid>, Without<Player>)>,
+    mut selection: ResMut<BoidSelection>,
+    mut contexts: EguiContexts,
+) {
+    if !mouse_input.just_pressed(MouseButton::Left) {
+        return;
+    }
+    // Clicks on the egui panels are not meant for the boids
+    if contexts
+        .try_ctx_mut()
+        .is_some_and(|ctx| ctx.is_pointer_over_area())
+    {
+        return;
+    }
+    let Ok(window) = window_query.get_single() else {
+        return;
+    };
+    let Ok((camera, camera_transform)) = camera_query.get_single() else {
+        return;
+    };
+    let Some(cursor) = window
+        .cursor_position()
+        .and_then(|cursor| camera.viewport_to_world_2d(camera_transform, cursor))
+    else {
+        return;
+    };
+
+    let closest = boid_query
+        .iter()
+        .map(|(entity, transform)| {
+            let distance = transform.translation.truncate().distance(cursor);
+            (entity, distance)
+        })
+        .filter(|(_, distance)| *distance < PICK_RADIUS)
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(entity, _)| entity);
+
+    selection.entity = closest;
+}
+
+/// Move the `Selected` marker when the selection changes, leaving other marked boids alone
+fn sync_selected(
+    mut commands: Commands,
+    selection: Res<BoidSelection>,
+    mut previous: Local<Option<Entity>>,
+) {
+    if selection.entity == *previous {
+        return;
+    }
+    if let Some(mut entity) = previous.and_then(|entity| commands.get_entity(entity)) {
+        entity.remove::<Selected>();
+    }
+    if let Some(mut entity) = selection
+        .entity
+        .and_then(|entity| commands.get_entity(entity))
+    {
+        entity.insert(Selected);
+    }
+    *previous = selection.entity;
+}
+
+/// Move the camera onto the selected boid
+fn follow_selection(
+    selection: Res<BoidSelection>,
+    boid_query: Query<&Transform, (With<Boid>, Without<Camera>)>,
+    mut camera_query: Query<&mut Transform, With<Camera>>,
+) {
+    if !selection.follow_camera {
+        return;
+    }
+    let Some(boid) = selection
+        .entity
+        .and_then(|entity| boid_query.get(entity).ok())
+    else {
+        return;
+    };
+    for mut camera_transform in camera_query.iter_mut() {
+        camera_transform.translation.x = boid.translation.x;
+        camera_transform.translation.y = boid.translation.y;
+    }
+}
+
+/// Circle the selected boid and draw lines to the neighbours it currently sees
+fn highlight_neighbours(
+    mut gizmos: Gizmos,
+    selection: Res<BoidSelection>,
+    treeaccess: Res<KDTree2<Boid>>,
+    boid_query: Query<&Transform, With<Boid>>,
+    settings: Res<Settings>,
+) {
+    let Some(entity) = selection.entity else {
+        return;
+    };
+    let Ok(transform) = boid_query.get(entity) else {
+        return;
+    };
+    let position = transform.translation.truncate();
+    gizmos.circle_2d(position, PICK_RADIUS, SELECTION_COLOUR);
+    for (_, neighbour) in treeaccess.within_distance(position, settings.visual_radius) {
+        let Some(neighbour) = neighbour.filter(|neighbour| *neighbour != entity) else {
+            continue;
+        };
+        let Ok(neighbour_transform) = boid_query.get(neighbour) else {
+            continue;
+        };
+        let neighbour_position = neighbour_transform.translation.truncate();
+        if neighbour_position.distance(position) < settings.visual_radius {
+            gizmos.line_2d(position, neighbour_position, NEIGHBOUR_COLOUR);
+            gizmos.circle_2d(neighbour_position, 5.0, NEIGHBOUR_COLOUR);
+        }
+    }
+}
+
+/// Show the state of the selected boid and the forces acting on it
+#[allow(clippy::too_many_arguments)]
+fn selection_panel(
+    mut contexts: EguiContexts,
+    mut selection: ResMut<BoidSelection>,
+    treeaccess: Res<KDTree2<Boid>>,
+    bounds: Res<SimulationBounds>,
+    boid_query: Query<(
+        &Transform,
+        &Velocity,
+        &Acceleration,
+        Option<&BoidParameters>,
+        Option<&FlockForces>,
+    )>,
+    neighbour_query: Query<(&Transform, &Velocity, &Boid)>,
+    settings: Res<Settings>,
+) {
+    let Some(entity) = selection.entity else {
+        return;
+    };
+    let Ok((transform, velocity, acceleration, parameters, recorded)) = boid_query.get(entity)
+    else {
+        // The selected boid was despawned
+        selection.entity = None;
+        return;
+    };
+    let Some(ctx) = contexts.try_ctx_mut() else {
+        return;
+    };
+
+    let position = transform.translation.truncate();
+    // Use the forces the boid recorded while flocking, or work them out if it doesn't record them
+    let forces = FlockForces::recorded_or_compute(
+        recorded,
+        position,
+        velocity.0,
+        parameters,
+        &treeaccess,
+        &neighbour_query,
+        bounds.0,
+        &settings,
+    );
+
+    let mut open = true;
+    egui::Window::new("Selected boid")
+        .open(&mut open)
+        .show(ctx, |ui| {
+            ui.label(format!("Entity: {entity}"));
+            ui.label(format!("Position: {}", format_vec(position)));
+            ui.label(format!(
+                "Velocity: {} ({:.1})",
+                format_vec(velocity.0),
+                velocity.0.length()
+            ));
+            ui.label(format!("Acceleration: {}", format_vec(acceleration.0)));
+            ui.label(format!("Neighbours: {}", forces.neighbours));
+            ui.separator();
+            if settings.model != FlockingModel::Reynolds {
+                ui.label(format!(
+                    "The flock uses the {:?} model, these are the Reynolds forces",
+                    settings.model
+                ));
+            }
+            egui::Grid::new("forces").striped(true).show(ui, |ui| {
+                for (name, force) in [
+                    ("Alignment", forces.alignment),
+                    ("Cohesion", forces.cohesion),
+                    ("Separation", forces.separation),
+                    ("Border", forces.border),
+                    ("Total", forces.total()),
+                ] {
+                    ui.label(name);
+                    ui.label(format_vec(force));
+                    ui.label(format!("{:.1}", force.length()));
+                    ui.end_row();
+                }
+            });
+            ui.separator();
+            ui.checkbox(&mut selection.follow_camera, "Follow with camera");
+        });
+    if !open {
+        selection.entity = None;
+    }
+}
+
+fn format_vec(vec: Vec2) -> String {
+    format!("({:.1}, {:.1})", vec.x, vec.y)
+}
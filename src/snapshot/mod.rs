@@ -0,0 +1,196 @@
+use crate::appearance::{BoidSize, Tint};
+use crate::boid::{
+    Acceleration, Boid, BoidParameters, FlockForces, HeadingNoise, Mass, MaxTurnRate, MaxVelocity,
+    Settings, Velocity,
+};
+use crate::emitter::{BoidEmitter, BoidSink};
+use crate::flow_field::FlowSource;
+use crate::formation::FormationTarget;
+use crate::path::{FollowPath, Path};
+use crate::player::components::Player;
+use crate::schedule::SimulationSet;
+use crate::steering::Steering;
+use bevy::input::common_conditions::input_just_pressed;
+use bevy::prelude::*;
+use bevy::tasks::IoTaskPool;
+use std::fs;
+
+#[allow(unused_imports)]
+pub mod prelude {
+    pub use super::{simulation_scene, SnapshotPlugin, SNAPSHOT_PATH};
+}
+
+/// F5 saves the simulation to a scene file and F9 loads it back
+pub struct SnapshotPlugin;
+
+impl Plugin for SnapshotPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PendingSnapshot>();
+        app.add_systems(
+            Update,
+            (
+                save_snapshot.run_if(input_just_pressed(KeyCode::F5)),
+                load_snapshot.run_if(input_just_pressed(KeyCode::F9)),
+                spawn_snapshot,
+            )
+                .chain()
+                .before(SimulationSet::RenderSync),
+        );
+    }
+}
+
+/// Scene file the snapshot is saved to, relative to the `assets/` folder
+pub const SNAPSHOT_PATH: &str = "scenes/simulation.scn.ron";
+
+/// Entities that are part of a snapshot, the player is left to the PlayerPlugin
+type SnapshotFilter = (
+    Or<(
+        With<Boid>,
+        With<BoidEmitter>,
+        With<BoidSink>,
+        With<FlowSource>,
+        With<Path>,
+    )>,
+    Without<Player>,
+);
+
+/// Snapshot scene being loaded, spawned once the asset server has finished reading it
+#[derive(Resource, Default)]
+struct PendingSnapshot(Option<Handle<DynamicScene>>);
+
+/// Build a scene of the simulation: the Settings and the simulation components of the boids,
+/// emitters, sinks, flow sources and paths. Meshes, materials and trails are left out,
+/// loaded boids get them from `BoidSpawner::attach_visuals`
+pub fn simulation_scene(world: &mut World) -> DynamicScene {
+    let entities: Vec<Entity> = world
+        .query_filtered::<Entity, SnapshotFilter>()
+        .iter(world)
+        .collect();
+    DynamicSceneBuilder::from_world(world)
+        .deny_all()
+        .allow::<Transform>()
+        .allow::<Boid>()
+        .allow::<Velocity>()
+        .allow::<Acceleration>()
+        .allow::<MaxVelocity>()
+        .allow::<MaxTurnRate>()
+        .allow::<Mass>()
+        .allow::<HeadingNoise>()
+        .allow::<BoidParameters>()
+        .allow::<FlockForces>()
+        .allow::<BoidSize>()
+        .allow::<Tint>()
+        .allow::<Steering>()
+        .allow::<FormationTarget>()
+        .allow::<FollowPath>()
+        .allow::<Path>()
+        .allow::<BoidEmitter>()
+        .allow::<BoidSink>()
+        .allow::<FlowSource>()
+        .deny_all_resources()
+        .allow_resource::<Settings>()
+        .extract_entities(entities.into_iter())
+        .extract_resources()
+        .build()
+}
+
+/// Write the simulation scene to `assets/` on a background thread
+fn save_snapshot(world: &mut World) {
+    let scene = simulation_scene(world);
+    let registry = world.resource::<AppTypeRegistry>().read();
+    let serialized = match scene.serialize(&registry) {
+        Ok(serialized) => serialized,
+        Err(error) => {
+            error!("Failed to serialize the simulation: {error}");
+            return;
+        }
+    };
+
+    let path = std::path::Path::new("assets").join(SNAPSHOT_PATH);
+    IoTaskPool::get()
+        .spawn(async move {
+            let result = path
+                .parent()
+                .map_or(Ok(()), fs::create_dir_all)
+                .and_then(|_| fs::write(&path, serialized));
+            match result {
+                Ok(()) => info!("Saved the simulation to {}", path.display()),
+                Err(error) => error!("Failed to save the simulation: {error}"),
+            }
+        })
+        .detach();
+}
+
+/// Start reading the snapshot, reloading it in case it was saved again since the last load
+fn load_snapshot(asset_server: Res<AssetServer>, mut pending: ResMut<PendingSnapshot>) {
+    let handle = asset_server.load(SNAPSHOT_PATH);
+    if asset_server.is_loaded_with_dependencies(&handle) {
+        asset_server.reload(SNAPSHOT_PATH);
+    }
+    pending.0 = Some(handle);
+}
+
+/// Replace the simulation with the snapshot once it has been read
+fn spawn_snapshot(
+    mut commands: Commands,
+    mut events: EventReader<AssetEvent<DynamicScene>>,
+    mut pending: ResMut<PendingSnapshot>,
+    mut scene_spawner: ResMut<SceneSpawner>,
+    snapshot_query: Query<Entity, SnapshotFilter>,
+) {
+    let Some(id) = pending.0.as_ref().map(|handle| handle.id()) else {
+        events.clear();
+        return;
+    };
+    let loaded = events.read().any(|event| {
+        matches!(
+            event,
+            AssetEvent::LoadedWithDependencies { id: loaded } | AssetEvent::Modified { id: loaded }
+                if *loaded == id
+        )
+    });
+    if !loaded {
+        return;
+    }
+
+    // Trails of the removed boids finish on their own
+    for entity in snapshot_query.iter() {
+        commands.entity(entity).despawn();
+    }
+    if let Some(handle) = pending.0.take() {
+        scene_spawner.spawn_dynamic(handle);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::appearance::AppearancePlugin;
+    use bevy::ecs::entity::EntityHashMap;
+
+    #[test]
+    fn tint_and_size_survive_a_round_trip() {
+        let mut app = App::new();
+        app.add_plugins(AppearancePlugin);
+        app.register_type::<Transform>().register_type::<Boid>();
+        let world = app.world_mut();
+        world.spawn((
+            Transform::from_xyz(10.0, 20.0, 0.0),
+            Boid { weight: 1.0 },
+            Tint(2),
+            BoidSize(1.5),
+        ));
+        let scene = simulation_scene(world);
+
+        let mut loaded = World::new();
+        loaded.insert_resource(world.resource::<AppTypeRegistry>().clone());
+        scene
+            .write_to_world(&mut loaded, &mut EntityHashMap::default())
+            .unwrap();
+        let (tint, size) = loaded
+            .query_filtered::<(&Tint, &BoidSize), With<Boid>>()
+            .single(&loaded);
+        assert_eq!(tint.0, 2);
+        assert_eq!(size.0, 1.5);
+    }
+}
@@ -0,0 +1,233 @@
+use crate::random::standard_normal;
+use bevy::prelude::*;
+use rand::Rng;
+use std::f32::consts::TAU;
+
+#[allow(unused_imports)]
+pub mod prelude {
+    pub use super::{SpawnHeading, SpawnPattern, SpawnVelocity};
+}
+
+/// How the initial positions of the flock are laid out inside the spawn area
+#[derive(Reflect, Clone, Copy, Default, PartialEq, Debug)]
+pub enum SpawnPattern {
+    /// Uniformly over the whole spawn area
+    #[default]
+    UniformRect,
+    /// Uniformly inside a disc at the centre of the spawn area
+    Disc { radius: f32 },
+    /// Uniformly inside a ring at the centre of the spawn area
+    Ring {
+        inner_radius: f32,
+        outer_radius: f32,
+    },
+    /// On a regular grid filling the spawn area
+    Grid,
+    /// Normally distributed around cluster centres placed uniformly in the spawn area
+    GaussianClusters { clusters: u32, std_dev: f32 },
+    /// Uniformly over the spawn area with no two boids closer than `min_distance`.
+    /// Fewer boids are spawned if the area fills up
+    PoissonDisk { min_distance: f32 },
+}
+
+impl SpawnPattern {
+    /// Attempts at placing a new Poisson-disk point around an existing one before giving up
+    const POISSON_ATTEMPTS: u32 = 30;
+
+    /// Sample `count` positions inside `area`
+    pub fn positions(&self, area: Rect, count: u32, rng: &mut impl Rng) -> Vec<Vec2> {
+        let centre = area.center();
+        match *self {
+            SpawnPattern::UniformRect => (0..count).map(|_| uniform_rect(area, rng)).collect(),
+            SpawnPattern::Disc { radius } => (0..count)
+                .map(|_| centre + uniform_ring(0.0, radius, rng))
+                .collect(),
+            SpawnPattern::Ring {
+                inner_radius,
+                outer_radius,
+            } => (0..count)
+                .map(|_| centre + uniform_ring(inner_radius, outer_radius, rng))
+                .collect(),
+            SpawnPattern::Grid => grid(area, count),
+            SpawnPattern::GaussianClusters { clusters, std_dev } => {
+                let centres: Vec<Vec2> = (0..clusters.max(1))
+                    .map(|_| uniform_rect(area, rng))
+                    .collect();
+                (0..count as usize)
+                    .map(|i| {
+                        let offset = Vec2::new(standard_normal(rng), standard_normal(rng));
+                        centres[i % centres.len()] + offset * std_dev
+                    })
+                    .collect()
+            }
+            SpawnPattern::PoissonDisk { min_distance } => {
+                poisson_disk(area, count, min_distance, rng)
+            }
+        }
+    }
+}
+
+/// Direction the boids initially move in
+#[derive(Reflect, Clone, Copy, Default, PartialEq, Debug)]
+pub enum SpawnHeading {
+    /// A uniformly random direction for every boid
+    #[default]
+    Random,
+    /// Already flocking: every boid moves at the given angle in radians,
+    /// perturbed by up to half of `spread` either way
+    Aligned { angle: f32, spread: f32 },
+    /// Away from the centre of the spawn area
+    Outward,
+    /// Anticlockwise around the centre of the spawn area
+    Swirl,
+}
+
+/// Initial velocity of the boids, made up of a heading and a speed
+#[derive(Reflect, Clone, Copy, PartialEq, Debug)]
+pub struct SpawnVelocity {
+    pub heading: SpawnHeading,
+    /// Slowest initial speed as a fraction of the boid's max speed
+    pub min_speed: f32,
+    /// Fastest initial speed as a fraction of the boid's max speed
+    pub max_speed: f32,
+}
+
+impl Default for SpawnVelocity {
+    fn default() -> Self {
+        Self {
+            heading: SpawnHeading::Random,
+            min_speed: 1.0,
+            max_speed: 1.0,
+        }
+    }
+}
+
+impl SpawnVelocity {
+    /// Create a new SpawnVelocity at full speed with the given heading
+    pub fn new(heading: SpawnHeading) -> Self {
+        Self {
+            heading,
+            ..default()
+        }
+    }
+
+    /// Set the range of initial speeds as fractions of the boid's max speed
+    pub fn with_speed(mut self, min_speed: f32, max_speed: f32) -> Self {
+        self.min_speed = min_speed;
+        self.max_speed = max_speed;
+        self
+    }
+
+    /// Sample the velocity of a boid spawned at `position`
+    pub fn sample(&self, position: Vec2, centre: Vec2, max_speed: f32, rng: &mut impl Rng) -> Vec2 {
+        let heading = match self.heading {
+            SpawnHeading::Random => random_heading(rng),
+            SpawnHeading::Aligned { angle, spread } => {
+                Vec2::from_angle(angle + (rng.gen::<f32>() - 0.5) * spread)
+            }
+            SpawnHeading::Outward => (position - centre)
+                .try_normalize()
+                .unwrap_or_else(|| random_heading(rng)),
+            SpawnHeading::Swirl => (position - centre)
+                .try_normalize()
+                .map_or_else(|| random_heading(rng), |outward| outward.perp()),
+        };
+        let (min, max) = (
+            self.min_speed.min(self.max_speed),
+            self.max_speed.max(self.min_speed),
+        );
+        heading * max_speed * (min + rng.gen::<f32>() * (max - min))
+    }
+}
+
+fn random_heading(rng: &mut impl Rng) -> Vec2 {
+    Vec2::from_angle(rng.gen::<f32>() * TAU)
+}
+
+fn uniform_rect(area: Rect, rng: &mut impl Rng) -> Vec2 {
+    area.min + Vec2::new(rng.gen(), rng.gen()) * area.size()
+}
+
+/// Uniform over the area of the ring, not its radius, so the centre isn't denser
+fn uniform_ring(inner_radius: f32, outer_radius: f32, rng: &mut impl Rng) -> Vec2 {
+    let (inner, outer) = (inner_radius.max(0.0), outer_radius.max(0.0));
+    let radius = (inner * inner + rng.gen::<f32>() * (outer * outer - inner * inner)).sqrt();
+    Vec2::from_angle(rng.gen::<f32>() * TAU) * radius
+}
+
+/// Fill the area row by row with cells as close to square as possible
+fn grid(area: Rect, count: u32) -> Vec<Vec2> {
+    if count == 0 {
+        return Vec::new();
+    }
+    let size = area.size().max(Vec2::splat(f32::EPSILON));
+    let columns = ((count as f32 * size.x / size.y).sqrt().ceil() as u32).clamp(1, count);
+    let rows = count.div_ceil(columns);
+    let cell = size / Vec2::new(columns as f32, rows as f32);
+    (0..count)
+        .map(|i| {
+            let index = Vec2::new((i % columns) as f32, (i / columns) as f32);
+            area.min + (index + 0.5) * cell
+        })
+        .collect()
+}
+
+/// Bridson's Poisson-disk sampling, grows the set of points outwards from a random start
+fn poisson_disk(area: Rect, count: u32, min_distance: f32, rng: &mut impl Rng) -> Vec<Vec2> {
+    if count == 0 {
+        return Vec::new();
+    }
+    if min_distance <= 0.0 {
+        return (0..count).map(|_| uniform_rect(area, rng)).collect();
+    }
+
+    // Each background grid cell can hold at most one point
+    let cell_size = min_distance / std::f32::consts::SQRT_2;
+    let columns = (area.width() / cell_size).ceil().max(1.0) as usize;
+    let rows = (area.height() / cell_size).ceil().max(1.0) as usize;
+    let mut cells: Vec<Option<usize>> = vec![None; columns * rows];
+    let cell_of = |point: Vec2| {
+        let index = ((point - area.min) / cell_size).as_uvec2();
+        (
+            (index.x as usize).min(columns - 1),
+            (index.y as usize).min(rows - 1),
+        )
+    };
+
+    let mut points = vec![uniform_rect(area, rng)];
+    let (x, y) = cell_of(points[0]);
+    cells[y * columns + x] = Some(0);
+    let mut active = vec![0];
+
+    while !active.is_empty() && points.len() < count as usize {
+        let active_index = rng.gen_range(0..active.len());
+        let origin = points[active[active_index]];
+        let mut placed = false;
+        for _ in 0..SpawnPattern::POISSON_ATTEMPTS {
+            let candidate = origin + uniform_ring(min_distance, 2.0 * min_distance, rng);
+            if !area.contains(candidate) {
+                continue;
+            }
+            let (x, y) = cell_of(candidate);
+            let too_close = (y.saturating_sub(2)..(y + 3).min(rows)).any(|ny| {
+                (x.saturating_sub(2)..(x + 3).min(columns)).any(|nx| {
+                    cells[ny * columns + nx].is_some_and(|other| {
+                        points[other].distance_squared(candidate) < min_distance * min_distance
+                    })
+                })
+            });
+            if too_close {
+                continue;
+            }
+            cells[y * columns + x] = Some(points.len());
+            active.push(points.len());
+            points.push(candidate);
+            placed = true;
+            break;
+        }
+        if !placed {
+            active.swap_remove(active_index);
+        }
+    }
+    points
+}
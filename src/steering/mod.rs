@@ -0,0 +1,561 @@
+use crate::boid::{
+    limit_vec, resolve_max_speed, Acceleration, Boid, BoidParameters, MaxVelocity, Settings,
+    Velocity,
+};
+use crate::random::SimulationRng;
+use crate::schedule::SteerSet;
+use bevy::ecs::entity::{EntityMapper, MapEntities};
+use bevy::ecs::reflect::ReflectMapEntities;
+use bevy::prelude::*;
+use bevy_spatial::{kdtree::KDTree2, SpatialAccess};
+use rand::Rng;
+
+#[allow(unused_imports)]
+pub mod prelude {
+    pub use super::{
+        InfluenceKernel, Neighbourhood, Steering, SteeringBehaviour, SteeringCombination,
+        SteeringPlugin, WeightedBehaviour,
+    };
+}
+
+pub struct SteeringPlugin;
+
+impl Plugin for SteeringPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<Steering>();
+        app.add_systems(FixedUpdate, steer.in_set(SteerSet::Rules));
+    }
+}
+
+/// A single steering behaviour, each one produces a force that is combined by `Steering`
+#[allow(dead_code)]
+#[derive(Reflect, Clone)]
+pub enum SteeringBehaviour {
+    /// Move towards a fixed point at full speed
+    Seek(Vec2),
+    /// Move away from a fixed point at full speed
+    Flee(Vec2),
+    /// Move towards a fixed point, slowing down once inside the slowing radius
+    Arrive { target: Vec2, slowing_radius: f32 },
+    /// Seek the predicted future position of another entity
+    Pursue(Entity),
+    /// Flee the predicted future position of another entity
+    Evade(Entity),
+    /// Randomly drift around by steering towards a point on a circle in front of the entity
+    Wander {
+        /// Radius of the wander circle
+        radius: f32,
+        /// Distance of the wander circle in front of the entity
+        distance: f32,
+        /// How far the target on the circle can move per second, in radians
+        jitter: f32,
+        /// Current angle of the target on the wander circle
+        angle: f32,
+    },
+    /// Reynolds alignment rule, uses the boid neighbour index
+    Alignment,
+    /// Reynolds cohesion rule, uses the boid neighbour index
+    Cohesion,
+    /// Reynolds separation rule, uses the boid neighbour index
+    Separation,
+}
+
+#[allow(dead_code)]
+impl SteeringBehaviour {
+    /// Create a wander behaviour starting at a random angle on the wander circle.
+    /// Pass the `SimulationRng` to keep seeded runs repeatable
+    pub fn wander(radius: f32, distance: f32, jitter: f32, rng: &mut impl Rng) -> Self {
+        Self::Wander {
+            radius,
+            distance,
+            jitter,
+            angle: rng.gen::<f32>() * std::f32::consts::TAU,
+        }
+    }
+
+    fn uses_neighbours(&self) -> bool {
+        matches!(self, Self::Alignment | Self::Cohesion | Self::Separation)
+    }
+}
+
+/// A steering behaviour and the weight its force is multiplied by
+#[derive(Reflect, Clone)]
+pub struct WeightedBehaviour {
+    pub behaviour: SteeringBehaviour,
+    pub weight: f32,
+}
+
+/// How the forces of multiple behaviours are combined into a single acceleration
+#[allow(dead_code)]
+#[derive(Reflect, Clone, Copy, Default, PartialEq)]
+pub enum SteeringCombination {
+    /// Sum all weighted forces and limit the result to max force
+    #[default]
+    WeightedSum,
+    /// Accumulate weighted forces in list order until max force is used up,
+    /// so earlier behaviours take priority over later ones
+    Priority,
+}
+
+/// A list of steering behaviours for an entity with `Velocity` and `Acceleration`.
+/// Boids with this component are steered by it instead of the default flocking rules
+#[derive(Component, Reflect, Clone, Default)]
+#[reflect(Component, MapEntities)]
+pub struct Steering {
+    pub behaviours: Vec<WeightedBehaviour>,
+    pub combination: SteeringCombination,
+}
+
+#[allow(dead_code)]
+impl Steering {
+    /// Create a new Steering component with no behaviours
+    pub fn new(combination: SteeringCombination) -> Self {
+        Self {
+            behaviours: Vec::new(),
+            combination,
+        }
+    }
+
+    /// Add a behaviour with the given weight
+    pub fn with(mut self, behaviour: SteeringBehaviour, weight: f32) -> Self {
+        self.behaviours
+            .push(WeightedBehaviour { behaviour, weight });
+        self
+    }
+
+    /// Combine a list of already weighted forces using the combination mode
+    pub fn combine(&self, forces: &[Vec2], max_force: f32) -> Vec2 {
+        combine(forces, self.combination, max_force)
+    }
+}
+
+impl MapEntities for Steering {
+    fn map_entities<M: EntityMapper>(&mut self, entity_mapper: &mut M) {
+        for weighted in self.behaviours.iter_mut() {
+            if let SteeringBehaviour::Pursue(target) | SteeringBehaviour::Evade(target) =
+                &mut weighted.behaviour
+            {
+                *target = entity_mapper.map_entity(*target);
+            }
+        }
+    }
+}
+
+/// Combine a list of weighted forces into a single force
+pub fn combine(forces: &[Vec2], combination: SteeringCombination, max_force: f32) -> Vec2 {
+    match combination {
+        SteeringCombination::WeightedSum => {
+            let mut total = forces.iter().copied().sum::<Vec2>();
+            limit_vec(&mut total, max_force);
+            total
+        }
+        SteeringCombination::Priority => {
+            let mut total = Vec2::ZERO;
+            let mut remaining = max_force;
+            for force in forces {
+                let magnitude = force.length();
+                if magnitude >= remaining {
+                    total += force.clamp_length_max(remaining);
+                    break;
+                }
+                total += *force;
+                remaining -= magnitude;
+            }
+            total
+        }
+    }
+}
+
+/// Steer towards the target at max speed
+pub fn seek(position: Vec2, velocity: Vec2, target: Vec2, max_speed: f32, max_force: f32) -> Vec2 {
+    let mut force = (target - position).normalize_or_zero() * max_speed - velocity;
+    limit_vec(&mut force, max_force);
+    force
+}
+
+/// Steer away from the target at max speed
+pub fn flee(position: Vec2, velocity: Vec2, target: Vec2, max_speed: f32, max_force: f32) -> Vec2 {
+    let mut force = (position - target).normalize_or_zero() * max_speed - velocity;
+    limit_vec(&mut force, max_force);
+    force
+}
+
+/// Steer towards the target, slowing down linearly once inside the slowing radius
+pub fn arrive(
+    position: Vec2,
+    velocity: Vec2,
+    target: Vec2,
+    slowing_radius: f32,
+    max_speed: f32,
+    max_force: f32,
+) -> Vec2 {
+    let offset = target - position;
+    let distance = offset.length();
+    let speed = if distance < slowing_radius {
+        max_speed * distance / slowing_radius
+    } else {
+        max_speed
+    };
+    let mut force = offset.normalize_or_zero() * speed - velocity;
+    limit_vec(&mut force, max_force);
+    force
+}
+
+/// Predict where a moving target will be by the time we could reach it at max speed
+fn predict(position: Vec2, target: Vec2, target_velocity: Vec2, max_speed: f32) -> Vec2 {
+    if max_speed <= 0.0 {
+        return target;
+    }
+    let time = position.distance(target) / max_speed;
+    target + target_velocity * time
+}
+
+/// Seek the predicted future position of a moving target
+pub fn pursue(
+    position: Vec2,
+    velocity: Vec2,
+    target: Vec2,
+    target_velocity: Vec2,
+    max_speed: f32,
+    max_force: f32,
+) -> Vec2 {
+    let predicted = predict(position, target, target_velocity, max_speed);
+    seek(position, velocity, predicted, max_speed, max_force)
+}
+
+/// Flee the predicted future position of a moving target
+pub fn evade(
+    position: Vec2,
+    velocity: Vec2,
+    target: Vec2,
+    target_velocity: Vec2,
+    max_speed: f32,
+    max_force: f32,
+) -> Vec2 {
+    let predicted = predict(position, target, target_velocity, max_speed);
+    flee(position, velocity, predicted, max_speed, max_force)
+}
+
+/// Steer towards a point on a circle projected in front of the entity.
+/// The angle of that point is nudged randomly each call
+#[allow(clippy::too_many_arguments)]
+pub fn wander(
+    position: Vec2,
+    velocity: Vec2,
+    angle: &mut f32,
+    radius: f32,
+    distance: f32,
+    jitter: f32,
+    max_speed: f32,
+    max_force: f32,
+    delta_seconds: f32,
+    rng: &mut impl Rng,
+) -> Vec2 {
+    *angle += (rng.gen::<f32>() * 2.0 - 1.0) * jitter * delta_seconds;
+    let heading = velocity.normalize_or_zero();
+    let centre = position + heading * distance;
+    let target = centre + Vec2::from_angle(*angle) * radius;
+    seek(position, velocity, target, max_speed, max_force)
+}
+
+/// How a neighbour's influence falls off with distance inside a rule's radius.
+/// Every kernel except Constant reaches 0 at the radius, so neighbours fade in and out smoothly
+#[derive(Reflect, Clone, Copy, Default, PartialEq, Debug)]
+pub enum InfluenceKernel {
+    /// Every neighbour inside the radius counts equally
+    #[default]
+    Constant,
+    /// Falls off linearly from 1 at the centre to 0 at the radius
+    Linear,
+    /// Bell curve, close neighbours count fully and the influence fades near the radius
+    Gaussian,
+    /// Very close neighbours dominate, distant ones barely count
+    InverseSquare,
+}
+
+impl InfluenceKernel {
+    /// Weight between 0 and 1 of a neighbour at `distance` inside `radius`
+    pub fn weight(&self, distance: f32, radius: f32) -> f32 {
+        if radius <= 0.0 || distance >= radius {
+            return 0.0;
+        }
+        let q = distance / radius;
+        match self {
+            Self::Constant => 1.0,
+            Self::Linear => 1.0 - q,
+            Self::Gaussian => {
+                // Shifted and rescaled so it is exactly 0 at the radius
+                const SHARPNESS: f32 = 4.5;
+                let edge = (-SHARPNESS).exp();
+                ((-SHARPNESS * q * q).exp() - edge) / (1.0 - edge)
+            }
+            Self::InverseSquare => {
+                // Softened so it stays finite at 0, rescaled to go from 1 at the centre to 0 at the radius
+                const SOFTENING: f32 = 0.05;
+                let edge = 1.0 / (1.0 + SOFTENING);
+                (1.0 / (q * q + SOFTENING) - edge) / (1.0 / SOFTENING - edge)
+            }
+        }
+    }
+}
+
+/// Sums of the neighbours of a boid, used by the alignment, cohesion and separation rules
+#[derive(Clone, Copy, Default)]
+pub struct Neighbourhood {
+    pub velocity_sum: Vec2,
+    pub position_sum: Vec2,
+    pub separation_sum: Vec2,
+    /// Total weight of the neighbours within the visual radius
+    pub weight_sum: f32,
+    /// Total weight of the neighbours within the separation radius
+    pub separation_weight_sum: f32,
+    /// Total weight of the neighbours after applying the alignment kernel
+    pub alignment_influence: f32,
+    /// Total weight of the neighbours after applying the cohesion kernel
+    pub cohesion_influence: f32,
+    /// Total weight of the neighbours after applying the separation kernel
+    pub separation_influence: f32,
+    pub count: u32,
+    pub separation_count: u32,
+}
+
+impl Neighbourhood {
+    /// Gather the neighbours of a position from the boid neighbour index
+    pub fn gather(
+        position: Vec2,
+        treeaccess: &KDTree2<Boid>,
+        neighbour_query: &Query<(&Transform, &Velocity, &Boid)>,
+        settings: &Settings,
+    ) -> Self {
+        let mut neighbourhood = Self::default();
+        for (_, entity) in treeaccess.within_distance(position, settings.visual_radius) {
+            let Some(entity) = entity else {
+                continue;
+            };
+            if let Ok((other_transform, other_velocity, boid)) = neighbour_query.get(entity) {
+                neighbourhood.add(
+                    position,
+                    other_transform.translation.truncate(),
+                    other_velocity.0,
+                    boid.weight,
+                    settings,
+                );
+            }
+        }
+        neighbourhood
+    }
+
+    /// Add a single neighbour to the sums if it is within range
+    pub fn add(
+        &mut self,
+        position: Vec2,
+        other_position: Vec2,
+        other_velocity: Vec2,
+        weight: f32,
+        settings: &Settings,
+    ) {
+        if position == other_position {
+            return;
+        }
+        let distance = position.distance(other_position);
+        if distance < settings.visual_radius {
+            let alignment = weight
+                * settings
+                    .alignment_kernel
+                    .weight(distance, settings.visual_radius);
+            let cohesion = weight
+                * settings
+                    .cohesion_kernel
+                    .weight(distance, settings.visual_radius);
+            self.velocity_sum += other_velocity * alignment;
+            self.position_sum += other_position * cohesion;
+            self.alignment_influence += alignment;
+            self.cohesion_influence += cohesion;
+            self.weight_sum += weight;
+            self.count += 1;
+        }
+
+        if distance < settings.separation_radius {
+            let separation = weight
+                * settings
+                    .separation_kernel
+                    .weight(distance, settings.separation_radius);
+            let mut diff = position - other_position;
+            diff /= distance.max(0.000001);
+            self.separation_sum += diff * separation;
+            self.separation_influence += separation;
+            self.separation_weight_sum += weight;
+            self.separation_count += 1;
+        }
+    }
+
+    /// Alignment rule: steer towards the average heading of the neighbours
+    pub fn alignment(&self, velocity: Vec2, max_speed: f32, max_force: f32) -> Vec2 {
+        if self.count == 0 || self.alignment_influence <= 0.0 {
+            return Vec2::ZERO;
+        }
+        // Set the alignment to a direction multiplied by max speed so we are always travelling at
+        // max speed. This can be removed if you want the average velocity
+        let mut alignment = self.velocity_sum / self.alignment_influence;
+        // Neighbours whose headings cancel out give no direction to align to
+        let Some(direction) = alignment.try_normalize() else {
+            return Vec2::ZERO;
+        };
+        alignment = direction * max_speed;
+        alignment -= velocity;
+        limit_vec(&mut alignment, max_force);
+        // Scale by the average kernel weight so the force fades as neighbours reach the radius
+        alignment * self.alignment_influence / self.weight_sum
+    }
+
+    /// Cohesion rule: steer towards the average position of the neighbours
+    pub fn cohesion(&self, position: Vec2, velocity: Vec2, max_speed: f32, max_force: f32) -> Vec2 {
+        if self.count == 0 || self.cohesion_influence <= 0.0 {
+            return Vec2::ZERO;
+        }
+        // Weighted centre of mass, heavy neighbours pull it towards themselves
+        let mut cohesion = self.position_sum / self.cohesion_influence;
+        cohesion -= position;
+        let Some(direction) = cohesion.try_normalize() else {
+            return Vec2::ZERO;
+        };
+        cohesion = direction * max_speed;
+        cohesion -= velocity;
+        limit_vec(&mut cohesion, max_force);
+        cohesion * self.cohesion_influence / self.weight_sum
+    }
+
+    /// Separation rule: steer away from neighbours within the separation radius
+    pub fn separation(&self, velocity: Vec2, max_speed: f32, max_force: f32) -> Vec2 {
+        if self.separation_count == 0 || self.separation_influence <= 0.0 {
+            return Vec2::ZERO;
+        }
+        let mut separation = self.separation_sum / self.separation_influence;
+        let Some(direction) = separation.try_normalize() else {
+            return Vec2::ZERO;
+        };
+        separation = direction * max_speed;
+        separation -= velocity;
+        limit_vec(&mut separation, max_force);
+        separation * self.separation_influence / self.separation_weight_sum
+    }
+}
+
+/// Apply the steering behaviours of every entity with a `Steering` component
+pub fn steer(
+    treeaccess: Res<KDTree2<Boid>>,
+    mut steering_query: Query<(
+        &mut Steering,
+        &mut Acceleration,
+        &Transform,
+        &Velocity,
+        Option<&MaxVelocity>,
+        Option<&BoidParameters>,
+    )>,
+    target_query: Query<(&Transform, &Velocity)>,
+    neighbour_query: Query<(&Transform, &Velocity, &Boid)>,
+    settings: Res<Settings>,
+    time: Res<Time>,
+    mut rng: ResMut<SimulationRng>,
+) {
+    let mut forces: Vec<Vec2> = Vec::new();
+    for (mut steering, mut acceleration, transform, velocity, max_velocity, parameters) in
+        steering_query.iter_mut()
+    {
+        let position = transform.translation.truncate();
+        let velocity = velocity.0;
+        let max_speed = resolve_max_speed(max_velocity, parameters, &settings);
+        let max_force = settings.max_force;
+
+        // Only query the neighbour index if one of the Reynolds rules needs it
+        let neighbourhood = if steering
+            .behaviours
+            .iter()
+            .any(|b| b.behaviour.uses_neighbours())
+        {
+            Neighbourhood::gather(position, &treeaccess, &neighbour_query, &settings)
+        } else {
+            Neighbourhood::default()
+        };
+
+        forces.clear();
+        for weighted in steering.behaviours.iter_mut() {
+            let force = match &mut weighted.behaviour {
+                SteeringBehaviour::Seek(target) => {
+                    seek(position, velocity, *target, max_speed, max_force)
+                }
+                SteeringBehaviour::Flee(target) => {
+                    flee(position, velocity, *target, max_speed, max_force)
+                }
+                SteeringBehaviour::Arrive {
+                    target,
+                    slowing_radius,
+                } => arrive(
+                    position,
+                    velocity,
+                    *target,
+                    *slowing_radius,
+                    max_speed,
+                    max_force,
+                ),
+                SteeringBehaviour::Pursue(entity) => {
+                    let Ok((target_transform, target_velocity)) = target_query.get(*entity) else {
+                        continue;
+                    };
+                    let target = target_transform.translation.truncate();
+                    pursue(
+                        position,
+                        velocity,
+                        target,
+                        target_velocity.0,
+                        max_speed,
+                        max_force,
+                    )
+                }
+                SteeringBehaviour::Evade(entity) => {
+                    let Ok((target_transform, target_velocity)) = target_query.get(*entity) else {
+                        continue;
+                    };
+                    let target = target_transform.translation.truncate();
+                    evade(
+                        position,
+                        velocity,
+                        target,
+                        target_velocity.0,
+                        max_speed,
+                        max_force,
+                    )
+                }
+                SteeringBehaviour::Wander {
+                    radius,
+                    distance,
+                    jitter,
+                    angle,
+                } => wander(
+                    position,
+                    velocity,
+                    angle,
+                    *radius,
+                    *distance,
+                    *jitter,
+                    max_speed,
+                    max_force,
+                    time.delta_seconds(),
+                    &mut rng.0,
+                ),
+                SteeringBehaviour::Alignment => {
+                    neighbourhood.alignment(velocity, max_speed, max_force)
+                }
+                SteeringBehaviour::Cohesion => {
+                    neighbourhood.cohesion(position, velocity, max_speed, max_force)
+                }
+                SteeringBehaviour::Separation => {
+                    neighbourhood.separation(velocity, max_speed, max_force)
+                }
+            };
+            forces.push(force * weighted.weight);
+        }
+
+        acceleration.0 = steering.combine(&forces, max_force);
+    }
+}
@@ -0,0 +1,88 @@
+use crate::boid::Settings;
+use bevy::app::FixedMain;
+use bevy::prelude::*;
+
+/// Applies the pause, step and time scale Settings to virtual time and binds them to the
+/// keyboard. BoidPlugin does not add it, so host apps keep control of virtual time
+pub struct TimeControlPlugin;
+
+impl Plugin for TimeControlPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            PreUpdate,
+            (
+                time_control_input,
+                step_simulation.run_if(step_requested),
+                apply_time_controls,
+            )
+                .chain(),
+        );
+    }
+}
+
+/// Slowest and fastest time scale reachable with the keyboard
+const MIN_TIME_SCALE: f32 = 1.0 / 16.0;
+const MAX_TIME_SCALE: f32 = 16.0;
+
+/// Space toggles pause, Period steps a single tick, `[` and `]` halve and double
+/// the time scale and Backslash resets it
+fn time_control_input(keyboard_input: Res<ButtonInput<KeyCode>>, mut settings: ResMut<Settings>) {
+    if keyboard_input.just_pressed(KeyCode::Space) {
+        settings.paused = !settings.paused;
+    }
+    if keyboard_input.just_pressed(KeyCode::Period) {
+        settings.step = true;
+    }
+    if keyboard_input.just_pressed(KeyCode::BracketLeft) {
+        settings.time_scale = (settings.time_scale / 2.0).max(MIN_TIME_SCALE);
+    }
+    if keyboard_input.just_pressed(KeyCode::BracketRight) {
+        settings.time_scale = (settings.time_scale * 2.0).min(MAX_TIME_SCALE);
+    }
+    if keyboard_input.just_pressed(KeyCode::Backslash) {
+        settings.time_scale = 1.0;
+    }
+}
+
+fn step_requested(settings: Res<Settings>) -> bool {
+    settings.step
+}
+
+/// Run a single FixedUpdate tick and pause. Unpausing virtual time for a frame would run
+/// however many ticks fit into that frame instead
+fn step_simulation(world: &mut World) {
+    let mut settings = world.resource_mut::<Settings>();
+    settings.step = false;
+    settings.paused = true;
+
+    let mut fixed_time = world.resource_mut::<Time<Fixed>>();
+    let timestep = fixed_time.timestep();
+    fixed_time.advance_by(timestep);
+    // Systems in FixedUpdate read the generic Time, point it at the fixed clock for the tick
+    let fixed_time = world.resource::<Time<Fixed>>().as_generic();
+    *world.resource_mut::<Time>() = fixed_time;
+    world.run_schedule(FixedMain);
+    let virtual_time = world.resource::<Time<Virtual>>().as_generic();
+    *world.resource_mut::<Time>() = virtual_time;
+}
+
+/// Drive Bevy's virtual time from the pause and time scale settings
+fn apply_time_controls(mut virtual_time: ResMut<Time<Virtual>>, settings: Res<Settings>) {
+    // Setting the speed to a negative or non-finite value panics
+    let time_scale = if settings.time_scale.is_finite() {
+        settings.time_scale.max(0.0)
+    } else {
+        1.0
+    };
+    if virtual_time.relative_speed() != time_scale {
+        virtual_time.set_relative_speed(time_scale);
+    }
+
+    if settings.paused != virtual_time.is_paused() {
+        if settings.paused {
+            virtual_time.pause();
+        } else {
+            virtual_time.unpause();
+        }
+    }
+}
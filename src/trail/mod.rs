@@ -1,31 +1,59 @@
 mod systems;
 
+use crate::schedule::SimulationSet;
+use bevy::ecs::entity::{EntityMapper, MapEntities};
+use bevy::ecs::reflect::ReflectMapEntities;
 use bevy::prelude::*;
 use bevy::render::mesh::PrimitiveTopology;
 use bevy::render::render_asset::RenderAssetUsages;
 
 #[allow(unused_imports)]
 pub mod prelude {
-    pub use super::{FollowEntity, SimpleTrail2D, TrailBuilder, TrailColour, TrailPlugin};
+    pub use super::{
+        FollowEntity, SimpleTrail2D, TrailBuilder, TrailColour, TrailPlugin, TRAIL_MATERIAL,
+    };
 }
 
 pub struct TrailPlugin;
 
 impl Plugin for TrailPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(PostUpdate, systems::update_trail);
+        app.register_type::<SimpleTrail2D>()
+            .register_type::<FollowEntity>();
+        app.add_systems(
+            Update,
+            systems::update_trail.in_set(SimulationSet::RenderSync),
+        );
     }
 }
 
-#[derive(Component)]
-pub struct FollowEntity(Entity);
+/// Material shared by every trail that isn't given its own, the colours come from the mesh
+pub const TRAIL_MATERIAL: Handle<ColorMaterial> =
+    Handle::weak_from_u128(0x5f1c_2a4e_8b3d_4c71_9e06_d2a8_73b5_e419);
+
+#[derive(Component, Reflect)]
+#[reflect(Component, MapEntities)]
+pub struct FollowEntity(pub Entity);
+
+impl MapEntities for FollowEntity {
+    fn map_entities<M: EntityMapper>(&mut self, entity_mapper: &mut M) {
+        self.0 = entity_mapper.map_entity(self.0);
+    }
+}
 
-#[derive(Component)]
+#[derive(Component, Reflect)]
+#[reflect(Component)]
 pub struct SimpleTrail2D {
     pub thickness: f32,
     pub local_offset: Vec2,
     pub points: Vec<Vec2>,
     pub taper_end: bool,
+    /// Colour the trail is built with
+    pub colour: TrailColour,
+    /// Overrides the colour with a single colour fading out along the trail
+    pub tint: Option<Color>,
+    /// Whether the mesh colours currently come from the tint
+    tinted: bool,
 }
 
 impl SimpleTrail2D {
@@ -36,14 +64,22 @@ impl SimpleTrail2D {
             local_offset,
             points: vec,
             taper_end: true,
+            colour: TrailColour::single(Color::WHITE),
+            tint: None,
+            tinted: false,
         }
     }
+
+    /// Move every point of the trail to a single position
+    pub fn reset(&mut self, position: Vec2) {
+        self.points.fill(position);
+    }
 }
 
 /// Used to build a trail entity
 pub struct TrailBuilder {
     /// The entity that this trail is attached to.
-    /// It will follow this entity in PostUpdate
+    /// It will follow this entity in the RenderSync simulation set
     follow_entity: Entity,
     /// How many segments the trail will have
     segments: u16,
@@ -59,6 +95,8 @@ pub struct TrailBuilder {
     colour: TrailColour,
     /// The Z-depth of the trail
     depth: f32,
+    /// The material of the trail, None shares TRAIL_MATERIAL
+    material: Option<Handle<ColorMaterial>>,
 }
 
 #[allow(dead_code)]
@@ -74,6 +112,7 @@ impl TrailBuilder {
             local_offset: Vec2::ZERO,
             colour: TrailColour::single(Color::WHITE),
             depth: -1.0,
+            material: None,
         }
     }
 
@@ -84,13 +123,14 @@ impl TrailBuilder {
         materials: &mut ResMut<Assets<ColorMaterial>>,
         meshes: &mut ResMut<Assets<Mesh>>,
     ) -> Entity {
-        let trail_renderer = SimpleTrail2D::new(
+        let mut trail_renderer = SimpleTrail2D::new(
             self.segments,
             self.thickness,
             self.spawn_pos,
             self.local_offset,
         );
         let colours = self.colour.get_vertex_colours(self.segments);
+        trail_renderer.colour = self.colour;
         let trail_mesh = meshes.add(
             Mesh::new(
                 PrimitiveTopology::TriangleStrip,
@@ -99,18 +139,25 @@ impl TrailBuilder {
             // .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, vec![])
             .with_inserted_attribute(Mesh::ATTRIBUTE_COLOR, colours),
         );
-        let trail_mat = materials.add(ColorMaterial::from_color(Color::WHITE));
+        let trail_mat = self.material.unwrap_or_else(|| {
+            if !materials.contains(&TRAIL_MATERIAL) {
+                materials.insert(&TRAIL_MATERIAL, ColorMaterial::from_color(Color::WHITE));
+            }
+            TRAIL_MATERIAL
+        });
         let transform = Transform::from_xyz(0.0, 0.0, self.depth);
-        commands.spawn((
-            ColorMesh2dBundle {
-                mesh: trail_mesh.clone().into(),
-                material: trail_mat,
-                transform,
-                ..default()
-            },
-            trail_renderer,
-            FollowEntity(self.follow_entity),
-        )).id()
+        commands
+            .spawn((
+                ColorMesh2dBundle {
+                    mesh: trail_mesh.clone().into(),
+                    material: trail_mat,
+                    transform,
+                    ..default()
+                },
+                trail_renderer,
+                FollowEntity(self.follow_entity),
+            ))
+            .id()
     }
 
     /// Set the number of segments in the trail
@@ -148,9 +195,16 @@ impl TrailBuilder {
         self.depth = depth;
         self
     }
+
+    /// Use a material of your own instead of the shared TRAIL_MATERIAL
+    pub fn with_material(mut self, material: Handle<ColorMaterial>) -> Self {
+        self.material = Some(material);
+        self
+    }
 }
 
 /// Colour of the trail, can be a single colour or gradient
+#[derive(Reflect, Clone)]
 pub enum TrailColour {
     Gradient { start: Color, end: Color },
     Single(Color),
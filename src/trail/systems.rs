@@ -3,28 +3,41 @@ use bevy::prelude::*;
 use super::*;
 
 /// Update all the points in the trail based on the follow entity
-/// If the FollowEntity does not exist, the trail will be destroyed
+/// If the FollowEntity does not exist, the trail shrinks into its last position and is then destroyed
 pub (crate) fn update_trail(
     mut commands: Commands,
     transforms: Query<&Transform>,
     mut query: Query<(&mut SimpleTrail2D, &mut Mesh2dHandle, &FollowEntity, Entity)>,
     mut assets: ResMut<Assets<Mesh>>,
+    time: Res<Time>,
 ) {
+    // Freeze trails while the simulation is paused, otherwise they shrink into the head
+    if time.delta_seconds() == 0.0 {
+        return;
+    }
     for (mut trail_renderer, mesh, follow_entity, entity) in query.iter_mut() {
-        let Ok(follow) = transforms.get(follow_entity.0) else {
-            // FollowEntity not found, remove the trail
-            commands.entity(entity).despawn();
-            continue
+        let new_pos = match transforms.get(follow_entity.0) {
+            Ok(follow) => {
+                // Get offset based on rotation of follow
+                let offset = follow.rotation.mul_vec3(Vec3::new(
+                    trail_renderer.local_offset.x,
+                    trail_renderer.local_offset.y,
+                    0.0,
+                ));
+                follow.translation.xy() + offset.xy()
+            }
+            Err(_) => {
+                // FollowEntity not found, let the trail finish before removing it
+                let head = trail_renderer.points[0];
+                if !head.is_finite() || trail_renderer.points.iter().all(|point| *point == head) {
+                    commands.entity(entity).despawn();
+                    continue
+                }
+                head
+            }
         };
 
         let mut vertices: Vec<Vec3> = Vec::with_capacity(trail_renderer.points.len() * 2);
-        // Get offset based on rotation of follow
-        let offset = follow.rotation.mul_vec3(Vec3::new(
-            trail_renderer.local_offset.x,
-            trail_renderer.local_offset.y,
-            0.0,
-        ));
-        let offset = offset.xy();
 
         // Update the trail points from the end to the start
         for i in (1..trail_renderer.points.len()).rev() {
@@ -33,12 +46,34 @@ pub (crate) fn update_trail(
         }
 
         // Add the new point at the start
-        let new_pos = follow.translation.xy() + offset;
         update_trail_point(0, new_pos, &mut trail_renderer, &mut vertices);
 
+        // A single NaN point would corrupt the mesh for the whole length of the trail
+        if vertices.iter().any(|vertex| !vertex.is_finite()) {
+            if !new_pos.is_finite() {
+                // Wait until the follow entity has been recovered
+                continue;
+            }
+            warn!("Trail {:?} has non-finite vertices, resetting it", entity);
+            trail_renderer.reset(new_pos);
+            vertices = vec![new_pos.extend(0.0); trail_renderer.points.len() * 2];
+        }
+
         // Update the mesh
         let mesh = assets.get_mut(mesh.id()).unwrap();
         mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, vertices);
+
+        // Recolour while tinted, and once more to restore the colour when the tint is removed
+        let segments = trail_renderer.points.len() as u16;
+        if let Some(tint) = trail_renderer.tint {
+            let colour = TrailColour::gradient(tint, tint.with_alpha(0.0));
+            mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, colour.get_vertex_colours(segments));
+            trail_renderer.tinted = true;
+        } else if trail_renderer.tinted {
+            let colours = trail_renderer.colour.get_vertex_colours(segments);
+            mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, colours);
+            trail_renderer.tinted = false;
+        }
     }
 }
 
@@ -58,7 +93,7 @@ fn update_trail_point(
         false => trail_renderer.thickness,
     };
     let dir = new_pos - trail_renderer.points[i];
-    let perp = Vec3::new(dir.y, -dir.x, 0.0).normalize() * thickness / 2.0;
+    let perp = Vec3::new(dir.y, -dir.x, 0.0).normalize_or_zero() * thickness / 2.0;
     trail_renderer.points[i as usize] = new_pos;
     let point_vec3 = Vec3::new(
         trail_renderer.points[i as usize].x,